@@ -1,10 +1,9 @@
 
-use anyhow::anyhow;
 use colorize::AnsiColor;
 use inquire::{validator::Validation, Select, Text};
 use rusqlite::{Row};
 
-use crate::{db::{list_all, open_connection, tabular_output, DBentity, Tablular}, empty_validator, MenuState};
+use crate::{db::{list_all, open_connection, tabular_output, DBentity, Tablular}, empty_validator, error::{AppError, AppResult}, MenuState};
 
 #[derive(Debug, Clone)]
 pub struct ApiKey {
@@ -27,7 +26,7 @@ impl ApiKeyFor {
         }.to_string()
     }
 
-    fn from_str(text: String) -> Self {
+    pub fn from_str(text: String) -> Self {
         match text.as_str() {
             "email" => Self::Email,
             "leads" => Self::Leads,
@@ -52,8 +51,8 @@ impl Tablular for ApiKey {
 
 
 impl DBentity for ApiKey {
-    fn new(row: &Row) -> Self {
-        ApiKey { key: row.get(0).unwrap(), purpose: ApiKeyFor::from_str(row.get(1).unwrap()) }
+    fn new(row: &Row) -> Result<Self, rusqlite::Error> {
+        Ok(ApiKey { key: row.get(0)?, purpose: ApiKeyFor::from_str(row.get(1)?) })
     }
  
     fn table_name() -> &'static str {
@@ -65,23 +64,23 @@ impl DBentity for ApiKey {
     }
 
     fn insert_new(&self) -> Result<usize, rusqlite::Error>  {
-        open_connection().execute(
+        open_connection()?.execute(
         format!("INSERT INTO {} (key, for) VALUES (?1, ?2)", Self::table_name()).as_str(),
         (self.key.to_owned(), self.purpose.to_str()))
         
     }
 }
 
-pub fn api_key_handler() -> MenuState{
+pub fn api_key_handler() -> AppResult<MenuState>{
     //show all api keys
     //first get all
-    let all_api_keys = list_all::<ApiKey>(None, None).unwrap().items;
+    let all_api_keys = list_all::<ApiKey>(None, None)?.items;
     let options = vec!["List API keys".blue(), "Add new".blue(), "Back".red()];
-     let direction = Select::new("API Keys".green().as_str(),options.clone()).prompt().unwrap(); 
+     let direction = Select::new("API Keys".green().as_str(),options.clone()).prompt()?; 
         match options.iter().position(|x|{*x == direction}) {
-            Some(0) => list_all_api_keys(&all_api_keys),
+            Some(0) => Ok(list_all_api_keys(&all_api_keys)),
             Some(1) => add_new_api_key(all_api_keys),
-            _ => MenuState::Settings
+            _ => Ok(MenuState::Settings)
         
     }
 }
@@ -91,7 +90,7 @@ fn list_all_api_keys(all_api_keys: &Vec<ApiKey> )-> MenuState{
     MenuState::APIkeys
 }
 
-fn add_new_api_key(all_api_keys: Vec<ApiKey>)-> MenuState{
+pub fn add_new_api_key(all_api_keys: Vec<ApiKey>)-> AppResult<MenuState>{
     //prompt for api key
     println!("{}", "Add new API Key".green());
 
@@ -106,11 +105,11 @@ fn add_new_api_key(all_api_keys: Vec<ApiKey>)-> MenuState{
     };
 
     //api key
-    let api_key = Text::new("enter the API key".blue().as_str()).with_validators(&[Box::from(dup_validator), Box::from(empty_validator)]).prompt().unwrap();
+    let api_key = Text::new("enter the API key".blue().as_str()).with_validators(&[Box::from(dup_validator), Box::from(empty_validator)]).prompt()?;
 
     //now get the purpose
     let options = vec!["email", "leads", "both"];
-    let purpose = Select::new("what is the purpose of this API key ?".blue().as_str(), options).prompt().unwrap();
+    let purpose = Select::new("what is the purpose of this API key ?".blue().as_str(), options).prompt()?;
     
     //create the instance
     let api_key_instance = ApiKey {
@@ -123,7 +122,7 @@ fn add_new_api_key(all_api_keys: Vec<ApiKey>)-> MenuState{
         Ok(_) => println!("{} \n", "added successfully".blue()),
         Err(e) => println!("{} error: {:?} \n", "couldn't add the new API key".red(), e),
     }
-    MenuState::APIkeys
+    Ok(MenuState::APIkeys)
 }
 
 pub struct ApiKeyRotation {
@@ -132,16 +131,68 @@ pub struct ApiKeyRotation {
 }
 
 impl ApiKeyRotation {
-    pub fn rotate(&mut self) -> anyhow::Result<ApiKey>{
-        if self.active_index >= self.api_keys_avaialble.len() - 1 {
-            Err(anyhow!("all api keys have reached thier limit"))
+    pub fn rotate(&mut self) -> AppResult<ApiKey>{
+        if self.active_index + 1 >= self.api_keys_avaialble.len() {
+            Err(AppError::Provider("all api keys have reached thier limit".to_string()))
         }else{
             self.active_index+=1;
-            Ok(self.api_keys_avaialble[self.active_index].clone())
+            self.get()
+        }
+    }
+
+    /// rotates away from the key at `exhausted`, unless another worker already did
+    pub fn rotate_past(&mut self, exhausted: usize) -> AppResult<ApiKey> {
+        if self.active_index != exhausted {
+            return self.get();
+        }
+        self.rotate()
+    }
+
+    pub fn get(&self) -> AppResult<ApiKey> {
+        self.api_keys_avaialble
+            .get(self.active_index)
+            .cloned()
+            .ok_or_else(|| AppError::Other("no api key to send the request with".to_string()))
+    }
+}
+
+impl ApiKey {
+    /// the key with everything but its ends hidden, safe to show on screen
+    pub fn masked(&self) -> String {
+        let chars = self.key.chars().collect::<Vec<char>>();
+        if chars.len() <= 8 {
+            return "*".repeat(chars.len());
+        }
+        format!(
+            "{}…{}",
+            chars[..4].iter().collect::<String>(),
+            chars[chars.len() - 4..].iter().collect::<String>()
+        )
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotation(keys: &[&str]) -> ApiKeyRotation {
+        ApiKeyRotation {
+            active_index: 0,
+            api_keys_avaialble: keys.iter().map(|key| ApiKey { key: key.to_string(), purpose: ApiKeyFor::Both }).collect(),
         }
     }
 
-    pub fn get(&self) -> ApiKey {
-        self.api_keys_avaialble[self.active_index].clone()
+    #[test]
+    fn no_keys_is_an_error() {
+        let mut keys = rotation(&[]);
+        assert!(keys.get().is_err());
+        assert!(keys.rotate().is_err());
+    }
+
+    #[test]
+    fn rotation_stops_at_the_last_key() {
+        let mut keys = rotation(&["first", "second"]);
+        assert_eq!(keys.rotate().unwrap().key, "second");
+        assert!(keys.rotate().is_err());
+        assert_eq!(keys.get().unwrap().key, "second");
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,199 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use colorize::AnsiColor;
+use inquire::{Confirm, Select};
+use rusqlite::{backup::Backup, Connection, OpenFlags};
+
+use crate::{
+    classify, clear_and_logo, config,
+    db::{open_connection, sqlite_path},
+    error::{AppError, AppResult},
+    jobs, profile, MenuState,
+};
+
+/// the database of the active profile
+fn database() -> AppResult<PathBuf> {
+    sqlite_path().ok_or_else(|| AppError::Other("no home directory found".to_string()))
+}
+
+/// the backups of a database are kept next to it
+fn backups_dir(database: &Path) -> PathBuf {
+    database.with_file_name("backups")
+}
+
+/// snapshots of the active profile, newest first
+fn snapshots() -> AppResult<Vec<PathBuf>> {
+    Ok(snapshots_in(&backups_dir(&database()?)))
+}
+
+fn snapshots_in(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut snapshots = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sqlite"))
+        .collect::<Vec<PathBuf>>();
+    //names start with the time they were taken at
+    snapshots.sort();
+    snapshots.reverse();
+    snapshots
+}
+
+/// copies the database of the active profile
+pub fn create(reason: &str) -> AppResult<PathBuf> {
+    create_of(&database()?, reason)
+}
+
+/// copies a database, then removes the oldest backups
+pub fn create_of(database: &Path, reason: &str) -> AppResult<PathBuf> {
+    let path = copy(database, reason)?;
+    prune(&backups_dir(database))?;
+    Ok(path)
+}
+
+/// copies a database with SQLite's online backup, safe while other connections are open
+fn copy(database: &Path, reason: &str) -> AppResult<PathBuf> {
+    let dir = backups_dir(database);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("apollo-{}-{}.sqlite", Local::now().format("%Y%m%d-%H%M%S%.3f"), reason));
+    let source = Connection::open(database)?;
+    let mut destination = Connection::open(&path)?;
+    Backup::new(&source, &mut destination)?.run_to_completion(256, Duration::from_millis(5), None)?;
+    log::info!("backup created reason={} file={}", reason, path.display());
+    Ok(path)
+}
+
+/// backs up before a fetch when `backup.before_jobs` is on, a failed backup doesn't stop the fetch
+pub fn before_job(reporter: &jobs::Reporter) {
+    if !config::get_bool("backup.before_jobs") {
+        return;
+    }
+    if let Err(e) = create("job") {
+        reporter.error(format!("couldn't back up the database: {}", e));
+    }
+}
+
+/// removes the oldest backups past `backup.keep`
+fn prune(dir: &Path) -> AppResult<()> {
+    let keep = config::get_u64("backup.keep").max(1) as usize;
+    for old in snapshots_in(dir).iter().skip(keep) {
+        fs::remove_file(old)?;
+        log::info!("backup removed file={}", old.display());
+    }
+    Ok(())
+}
+
+fn describe(path: &Path) -> String {
+    let name = path.file_stem().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let metadata = fs::metadata(path).ok();
+    let taken = metadata
+        .as_ref()
+        .and_then(|metadata| metadata.modified().ok())
+        .map(|time| DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let size = metadata.map(|metadata| metadata.len()).unwrap_or_default();
+    format!("{}  ({}, {} KB)", name, taken, size / 1024)
+}
+
+pub fn database_handler() -> AppResult<MenuState> {
+    let options = vec![
+        "Back up now".blue(),
+        "Restore a backup".blue(),
+        "Check and compact".blue(),
+        "Classify emails again".blue(),
+        "Back".red(),
+    ];
+    let selection = Select::new("Database".green().as_str(), options.clone()).prompt()?;
+    match options.iter().position(|x| *x == selection) {
+        Some(0) => {
+            let path = create("manual")?;
+            println!("{} {}\n", "backed up to".blue(), path.display());
+            Ok(MenuState::Database)
+        }
+        Some(1) => restore(),
+        Some(2) => check_and_compact(),
+        Some(3) => {
+            classify::reload();
+            let changed = classify::reclassify(&open_connection()?)?;
+            log::info!("emails classified again changed={}", changed);
+            println!("{} {}\n", "emails whose kind changed:".blue(), changed);
+            Ok(MenuState::Database)
+        }
+        _ => Ok(MenuState::Settings),
+    }
+}
+
+/// replaces the database with a snapshot, the current state is backed up first so a restore can be undone
+fn restore() -> AppResult<MenuState> {
+    if jobs::running() > 0 {
+        println!("{}", "wait for the running fetches to finish before restoring".red());
+        return Ok(MenuState::Database);
+    }
+    let snapshots = snapshots()?;
+    if snapshots.is_empty() {
+        println!("{}", "there are no backups yet".red());
+        return Ok(MenuState::Database);
+    }
+    clear_and_logo("Restore a backup".to_string());
+    let mut options = snapshots.iter().map(|path| describe(path)).collect::<Vec<String>>();
+    options.push("Back".to_string());
+    let selected = Select::new("Backups, newest first".green().as_str(), options).raw_prompt()?;
+    let Some(snapshot) = snapshots.get(selected.index) else {
+        return Ok(MenuState::Database);
+    };
+    if !Confirm::new("replace the current database with this backup ?")
+        .with_default(false)
+        .prompt()?
+    {
+        return Ok(MenuState::Database);
+    }
+
+    //read only, so a snapshot removed in the meantime is an error instead of a new empty database
+    let source = Connection::open_with_flags(snapshot, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| AppError::Other(format!("couldn't open the backup {}: {}", snapshot.display(), e)))?;
+    let database = database()?;
+    //pruning now could remove the snapshot being restored, it waits until the restore is done
+    let undo = copy(&database, "before-restore")?;
+    let mut destination = open_connection()?;
+    Backup::new(&source, &mut destination)?.run_to_completion(256, Duration::from_millis(5), None)?;
+    drop(destination);
+    log::info!("backup restored file={} undo={}", snapshot.display(), undo.display());
+    prune(&backups_dir(&database))?;
+    //a backup taken before an update lacks the newer tables and columns
+    profile::open(&profile::active())?;
+    println!("{} {}", "restored".blue(), describe(snapshot));
+    println!("{} {}\n", "the previous state was backed up to".blue(), undo.display());
+    Ok(MenuState::Database)
+}
+
+/// runs SQLite's integrity check, then VACUUM to give the space of deleted rows back
+fn check_and_compact() -> AppResult<MenuState> {
+    let connection = open_connection()?;
+    let problems = connection
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+    if problems != ["ok"] {
+        log::error!("integrity check failed problems={}", problems.len());
+        println!("{}", "the database is damaged, restore a backup:".red());
+        for problem in problems.iter().take(10) {
+            println!("  {}", problem);
+        }
+        return Ok(MenuState::Database);
+    }
+    println!("{}", "integrity check passed".blue());
+
+    let path = database()?;
+    let before = fs::metadata(&path)?.len();
+    connection.execute_batch("VACUUM")?;
+    let after = fs::metadata(&path)?.len();
+    log::info!("database compacted before={} after={}", before, after);
+    println!("{} {} KB -> {} KB\n", "compacted".blue(), before / 1024, after / 1024);
+    Ok(MenuState::Database)
+}
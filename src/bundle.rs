@@ -0,0 +1,376 @@
+use std::{collections::{BTreeMap, HashSet}, fs, path::Path};
+
+use chrono::Utc;
+use colorize::AnsiColor;
+use inquire::{validator::Validation, Confirm, MultiSelect, Select, Text};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apikey::{ApiKey, ApiKeyFor},
+    backup,
+    clear_and_logo,
+    db::{list_all, open_connection, DBentity},
+    empty_validator,
+    error::{AppError, AppResult},
+    import,
+    leads::{EmailConfidence, Lead, LeadField},
+    list::{EmployeeSize, List, ListFilter},
+    MenuState,
+};
+
+/// marks a file as a bundle, checked before anything is read from it
+const BUNDLE_FORMAT: &str = "apollo-bundle";
+
+/// bumped whenever the layout changes, bundles from newer versions are refused
+const BUNDLE_VERSION: u32 = 1;
+
+/// the key of a lead's email confidence, bundles written before it existed don't have it
+const CONFIDENCE_COLUMN: &str = "emailConfidence";
+
+/// lists, their filters and leads and optionally the API keys, moved between machines as one JSON file
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    format: String,
+    version: u32,
+    exported_at: String,
+    lists: Vec<BundledList>,
+    #[serde(default)]
+    api_keys: Vec<BundledApiKey>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundledList {
+    name: String,
+    leads_fetched: u32,
+    emails_fetched: u32,
+    next: Option<String>,
+    person: String,
+    location: String,
+    industry: String,
+    keywords: Option<String>,
+    employee_size: String,
+    /// in the order they were added, `emails_fetched` counts from the first one
+    leads: Vec<BundledLead>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundledLead {
+    id: String,
+    /// keyed by the column of each field, empty fields are left out
+    fields: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BundledApiKey {
+    key: String,
+    purpose: String,
+}
+
+impl BundledList {
+    fn from_list(list: &List) -> AppResult<Self> {
+        let leads = list_all::<Lead>(
+            Some(format!("SELECT * FROM {} WHERE listId = {} ORDER BY rowid", Lead::table_name(), list.id)),
+            None,
+        )?
+        .items;
+        Ok(Self {
+            name: list.name.clone(),
+            leads_fetched: list.leads_fetched,
+            emails_fetched: list.emails_fetched,
+            next: list.next_pointer.clone(),
+            person: list.filter.person_title.clone(),
+            location: list.filter.location.clone(),
+            industry: list.filter.industry.clone(),
+            keywords: list.filter.keywords.clone(),
+            employee_size: list.filter.employee_size.to_str(false),
+            leads: leads.iter().map(BundledLead::from_lead).collect(),
+        })
+    }
+
+    /// the list under another name, the ids are given by the database on insert
+    fn to_list(&self, name: String) -> AppResult<List> {
+        Ok(List {
+            id: 0,
+            name: name.clone(),
+            leads_fetched: self.leads.len() as u32,
+            emails_fetched: self.emails_fetched.min(self.leads.len() as u32),
+            next_pointer: self.next.clone(),
+            filter: ListFilter {
+                person_title: self.person.clone(),
+                location: self.location.clone(),
+                industry: self.industry.clone(),
+                keywords: self.keywords.clone(),
+                employee_size: EmployeeSize::from_str(self.employee_size.clone(), false)?,
+            },
+        })
+    }
+}
+
+impl BundledLead {
+    fn from_lead(lead: &Lead) -> Self {
+        let mut fields = LeadField::ALL
+            .into_iter()
+            .filter_map(|field| {
+                let value = lead.get(field).filter(|v| !v.is_empty())?;
+                Some((field.column().to_string(), value))
+            })
+            .collect::<BTreeMap<String, String>>();
+        if let Some(confidence) = lead.email_confidence {
+            fields.insert(CONFIDENCE_COLUMN.to_string(), confidence.to_str().to_string());
+        }
+        Self { id: lead.id.clone(), fields }
+    }
+
+    fn to_lead(&self, list_id: u32) -> Lead {
+        let mut lead = Lead::blank(self.id.clone(), list_id);
+        for field in LeadField::ALL {
+            lead.set(field, self.fields.get(field.column()).cloned());
+        }
+        lead.email_confidence = self
+            .fields
+            .get(CONFIDENCE_COLUMN)
+            .and_then(|confidence| EmailConfidence::from_str(confidence));
+        lead
+    }
+}
+
+pub fn export_handler() -> AppResult<MenuState> {
+    let options = vec![
+        "Export lists to a bundle".blue(),
+        "Import a bundle".blue(),
+        "Export leads to CSV".blue(),
+        "Back".red(),
+    ];
+    let direction = Select::new("Export".green().as_str(), options.clone()).prompt()?;
+    match options.iter().position(|x| *x == direction) {
+        Some(0) => export_bundle(),
+        Some(1) => import_bundle(),
+        Some(2) => import::export_csv(),
+        _ => Ok(MenuState::Main),
+    }
+}
+
+/// writes the selected lists with everything needed to keep fetching them elsewhere
+fn export_bundle() -> AppResult<MenuState> {
+    let lists = list_all::<List>(List::custom_query().map(|v| v.to_string()), None)?.items;
+    if lists.is_empty() {
+        println!("{}", "you don't have any lists to export".red());
+        return Ok(MenuState::Export);
+    }
+    clear_and_logo("Export".to_string());
+    let names = lists.iter().map(|list| list.name.clone()).collect::<Vec<String>>();
+    let selected = MultiSelect::new(
+        "Select the lists to export (use space to select multiple, enter to confirm):".blue().as_str(),
+        names,
+    )
+    .with_validator(|v: &[inquire::list_option::ListOption<&String>]| {
+        if v.is_empty() {
+            Ok(Validation::Invalid("please select a list".into()))
+        } else {
+            Ok(Validation::Valid)
+        }
+    })
+    .raw_prompt()?;
+    let with_keys = Confirm::new("include the API keys ? they are written in plain text")
+        .with_default(false)
+        .prompt()?;
+    let default_path = format!("apollo-{}.json", Utc::now().format("%Y%m%d-%H%M%S"));
+    let path = Text::new("save the bundle to:".blue().as_str())
+        .with_default(&default_path)
+        .with_validator(empty_validator)
+        .prompt()?;
+    let path = path.trim();
+    if Path::new(path).exists()
+        && !Confirm::new(format!("{} already exists, overwrite it ?", path).as_str())
+            .with_default(false)
+            .prompt()?
+    {
+        return Ok(MenuState::Export);
+    }
+
+    let bundle = Bundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        lists: selected
+            .iter()
+            .map(|option| BundledList::from_list(&lists[option.index]))
+            .collect::<AppResult<Vec<BundledList>>>()?,
+        api_keys: match with_keys {
+            true => list_all::<ApiKey>(None, None)?
+                .items
+                .into_iter()
+                .map(|api_key| BundledApiKey { key: api_key.key, purpose: api_key.purpose.to_str() })
+                .collect(),
+            false => Vec::new(),
+        },
+    };
+    let json = serde_json::to_string_pretty(&bundle).map_err(|e| AppError::Other(format!("couldn't write the bundle: {}", e)))?;
+    fs::write(path, json).map_err(|e| AppError::Other(format!("couldn't write {}: {}", path, e)))?;
+
+    let leads: usize = bundle.lists.iter().map(|list| list.leads.len()).sum();
+    log::info!(
+        "bundle exported file={} lists={} leads={} api_keys={}",
+        path,
+        bundle.lists.len(),
+        leads,
+        bundle.api_keys.len()
+    );
+    println!(
+        "{} {} {} {} {} {}\n",
+        "exported".blue(),
+        bundle.lists.len(),
+        "lists and".blue(),
+        leads,
+        "leads to".blue(),
+        path
+    );
+    Ok(MenuState::Export)
+}
+
+/// what to do with a bundled list named like one already in the database
+enum Conflict {
+    Rename(String),
+    Merge(Box<List>),
+    Skip,
+}
+
+/// merges a bundle into the database, everything is written in one transaction
+fn import_bundle() -> AppResult<MenuState> {
+    let path = Text::new("path of the bundle:".blue().as_str())
+        .with_validator(|v: &str| {
+            if Path::new(v.trim()).is_file() {
+                Ok(Validation::Valid)
+            } else {
+                Ok(Validation::Invalid("no file at this path".into()))
+            }
+        })
+        .prompt()?;
+    let path = path.trim();
+    let text = fs::read_to_string(path).map_err(|e| AppError::Other(format!("couldn't read {}: {}", path, e)))?;
+    let bundle: Bundle = serde_json::from_str(&text).map_err(|e| AppError::Parse(format!("the bundle {}: {}", path, e)))?;
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(AppError::Parse(format!("{}, it isn't an apollo bundle", path)));
+    }
+    if bundle.version > BUNDLE_VERSION {
+        return Err(AppError::Other(format!(
+            "{} was exported by a newer version (bundle version {}), update apollo to import it",
+            path, bundle.version
+        )));
+    }
+
+    //decide on every name conflict before writing anything
+    let existing = list_all::<List>(List::custom_query().map(|v| v.to_string()), None)?.items;
+    let mut taken = existing.iter().map(|list| list.name.clone()).collect::<HashSet<String>>();
+    let mut plan = Vec::with_capacity(bundle.lists.len());
+    for bundled in bundle.lists.iter() {
+        let Some(current) = existing.iter().find(|list| list.name == bundled.name) else {
+            taken.insert(bundled.name.clone());
+            plan.push(Conflict::Rename(bundled.name.clone()));
+            continue;
+        };
+        let renamed = free_name(&bundled.name, &taken);
+        let options = vec![
+            format!("import it as {}", renamed),
+            "add its leads to the existing list".to_string(),
+            "skip it".to_string(),
+        ];
+        let choice = Select::new(format!("a list named {} already exists:", bundled.name).as_str(), options).raw_prompt()?;
+        plan.push(match choice.index {
+            0 => {
+                taken.insert(renamed.clone());
+                Conflict::Rename(renamed)
+            }
+            1 => Conflict::Merge(Box::new(current.clone())),
+            _ => Conflict::Skip,
+        });
+    }
+
+    backup::create("import")?;
+    let mut connection = open_connection()?;
+    let transaction = connection.transaction()?;
+    let mut lists_added = 0;
+    let mut leads_added = 0;
+    let mut duplicates = 0;
+    for (bundled, conflict) in bundle.lists.iter().zip(plan) {
+        match conflict {
+            Conflict::Skip => continue,
+            Conflict::Rename(name) => {
+                //leads keep their order, so the email cursor still points at the same lead
+                let list_id = bundled.to_list(name)?.insert_with(&transaction)?;
+                for lead in bundled.leads.iter() {
+                    lead.to_lead(list_id).insert_with(&transaction)?;
+                }
+                lists_added += 1;
+                leads_added += bundled.leads.len();
+            }
+            Conflict::Merge(list) => {
+                //merged leads go after the existing ones, past the email cursor, so they get checked again
+                let mut ids = transaction
+                    .prepare(format!("SELECT id FROM {} WHERE listId = ?1", Lead::table_name()).as_str())?
+                    .query_map([list.id], |row| row.get::<_, String>(0))?
+                    .collect::<Result<HashSet<String>, rusqlite::Error>>()?;
+                let mut added = 0;
+                for lead in bundled.leads.iter() {
+                    if !ids.insert(lead.id.clone()) {
+                        duplicates += 1;
+                        continue;
+                    }
+                    lead.to_lead(list.id).insert_with(&transaction)?;
+                    added += 1;
+                }
+                transaction.execute(
+                    format!("UPDATE {} SET leadsFetched = leadsFetched + ?1 WHERE id = ?2", List::table_name()).as_str(),
+                    (added, list.id),
+                )?;
+                leads_added += added as usize;
+            }
+        }
+    }
+    let mut keys_added = 0;
+    for api_key in bundle.api_keys.iter() {
+        keys_added += transaction.execute(
+            format!("INSERT OR IGNORE INTO {} (key, for) VALUES (?1, ?2)", ApiKey::table_name()).as_str(),
+            (&api_key.key, ApiKeyFor::from_str(api_key.purpose.clone()).to_str()),
+        )?;
+    }
+    transaction.commit()?;
+
+    log::info!(
+        "bundle imported file={} lists={} leads={} duplicates={} api_keys={}",
+        path,
+        lists_added,
+        leads_added,
+        duplicates,
+        keys_added
+    );
+    println!(
+        "{} {} {} {} {}",
+        "imported".blue(),
+        lists_added,
+        "new lists and".blue(),
+        leads_added,
+        "leads".blue()
+    );
+    if duplicates > 0 {
+        println!("{} {}", "skipped leads already in the list:".blue(), duplicates);
+    }
+    if !bundle.api_keys.is_empty() {
+        println!(
+            "{} {} {}",
+            "added".blue(),
+            keys_added,
+            format!("of {} API keys, the others were already saved", bundle.api_keys.len()).blue()
+        );
+    }
+    println!();
+    Ok(MenuState::Export)
+}
+
+/// `name (2)`, `name (3)`... whichever isn't used yet
+fn free_name(name: &str, taken: &HashSet<String>) -> String {
+    (2..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap_or_default()
+}
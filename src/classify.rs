@@ -0,0 +1,218 @@
+use std::{
+    collections::HashSet,
+    fs,
+    sync::{LazyLock, RwLock},
+};
+
+use rusqlite::Connection;
+
+use crate::{db::DBentity, leads::Lead, profile};
+
+/// what kind of mailbox an email reaches
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EmailKind {
+    /// a person at a company domain
+    Personal,
+    /// a team or a function, like info@ or sales@
+    Role,
+    /// a mailbox provider anyone can sign up to
+    FreeMail,
+    /// a throwaway mailbox
+    Disposable,
+}
+
+impl EmailKind {
+    pub const ALL: [EmailKind; 4] = [Self::Personal, Self::Role, Self::FreeMail, Self::Disposable];
+
+    /// also how it is stored in the emailKind column
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Personal => "personal",
+            Self::Role => "role",
+            Self::FreeMail => "free mail",
+            Self::Disposable => "disposable",
+        }
+    }
+
+    pub fn from_str(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.to_str() == value)
+    }
+}
+
+/// a list bundled with apollo, or the file of the same name in the lists directory of the data directory,
+/// so a newer list can be dropped in without updating apollo
+fn load(name: &str, bundled: &'static str) -> HashSet<String> {
+    let path = profile::data_dir().map(|dir| dir.join("lists").join(name));
+    let text = match path {
+        Some(path) if path.is_file() => match fs::read_to_string(&path) {
+            Ok(text) => {
+                log::info!("list replaced file={}", path.display());
+                text
+            }
+            Err(e) => {
+                log::warn!("couldn't read list file={} error=\"{}\", using the bundled one", path.display(), e);
+                bundled.to_string()
+            }
+        },
+        _ => bundled.to_string(),
+    };
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// the lists emails are classified with
+struct Lists {
+    free_mail: HashSet<String>,
+    disposable: HashSet<String>,
+    roles: HashSet<String>,
+}
+
+impl Lists {
+    fn load() -> Self {
+        Self {
+            free_mail: load("free_mail_domains.txt", include_str!("data/free_mail_domains.txt")),
+            disposable: load("disposable_domains.txt", include_str!("data/disposable_domains.txt")),
+            roles: load("role_local_parts.txt", include_str!("data/role_local_parts.txt")),
+        }
+    }
+}
+
+static LISTS: LazyLock<RwLock<Lists>> = LazyLock::new(|| RwLock::new(Lists::load()));
+
+/// reads the lists again, picking up files dropped in the lists directory since they were read
+pub fn reload() {
+    *LISTS.write().unwrap_or_else(|e| e.into_inner()) = Lists::load();
+}
+
+/// whether the domain or one of its parents is in the list
+fn listed(list: &HashSet<String>, domain: &str) -> bool {
+    let mut domain = domain.trim_end_matches('.');
+    loop {
+        if list.contains(domain) {
+            return true;
+        }
+        match domain.split_once('.') {
+            Some((_, parent)) if parent.contains('.') => domain = parent,
+            _ => return false,
+        }
+    }
+}
+
+/// the kind of every mailbox of a domain, none for a company domain
+pub fn domain_kind(domain: &str) -> Option<EmailKind> {
+    let domain = domain.to_lowercase();
+    let lists = LISTS.read().unwrap_or_else(|e| e.into_inner());
+    if listed(&lists.disposable, &domain) {
+        Some(EmailKind::Disposable)
+    } else if listed(&lists.free_mail, &domain) {
+        Some(EmailKind::FreeMail)
+    } else {
+        None
+    }
+}
+
+/// the kind of an address, none when it isn't one
+pub fn kind(email: &str) -> Option<EmailKind> {
+    let email = email.trim().to_lowercase();
+    let (local_part, domain) = email.rsplit_once('@')?;
+    if let Some(kind) = domain_kind(domain) {
+        return Some(kind);
+    }
+    //info+leads@ and customer.service@ reach the same team as info@ and customerservice@
+    let local_part = local_part
+        .split('+')
+        .next()
+        .unwrap_or_default()
+        .replace(['.', '-', '_'], "");
+    match LISTS.read().unwrap_or_else(|e| e.into_inner()).roles.contains(&local_part) {
+        true => Some(EmailKind::Role),
+        false => Some(EmailKind::Personal),
+    }
+}
+
+/// migration storing the kind of every email already found
+pub fn migrate(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(&format!("ALTER TABLE {} ADD COLUMN emailKind TEXT;", Lead::table_name()))?;
+    reclassify(connection)?;
+    Ok(())
+}
+
+/// classifies every stored email again, the lists may have changed since, returns how many changed
+pub fn reclassify(connection: &Connection) -> Result<usize, rusqlite::Error> {
+    let leads = Lead::table_name();
+    let emails = connection
+        .prepare(&format!("SELECT rowid, email, emailKind FROM {} WHERE email IS NOT NULL", leads))?
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+        })?
+        .collect::<Result<Vec<(i64, String, Option<String>)>, rusqlite::Error>>()?;
+    let mut changed = 0;
+    for (rowid, email, stored) in emails {
+        let kind = kind(&email).map(|kind| kind.to_str().to_string());
+        if kind != stored {
+            connection.execute(&format!("UPDATE {} SET emailKind = ?1 WHERE rowid = ?2", leads), (kind, rowid))?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn company_addresses_are_personal_or_role() {
+        assert_eq!(kind("jane.doe@acme.com"), Some(EmailKind::Personal));
+        assert_eq!(kind("info@acme.com"), Some(EmailKind::Role));
+        assert_eq!(kind("Customer.Service+leads@Acme.com"), Some(EmailKind::Role));
+        assert_eq!(kind("no-reply@acme.com"), Some(EmailKind::Role));
+    }
+
+    #[test]
+    fn listed_domains_win_over_the_local_part() {
+        assert_eq!(kind("jane@gmail.com"), Some(EmailKind::FreeMail));
+        assert_eq!(kind("info@gmail.com"), Some(EmailKind::FreeMail));
+        assert_eq!(kind("jane@mailinator.com"), Some(EmailKind::Disposable));
+        //subdomains of a listed domain are listed too
+        assert_eq!(kind("jane@eu.mailinator.com"), Some(EmailKind::Disposable));
+        assert_eq!(domain_kind("acme.com"), None);
+    }
+
+    #[test]
+    fn not_an_address_has_no_kind() {
+        assert_eq!(kind("jane.doe"), None);
+    }
+
+    #[test]
+    fn kinds_read_back_from_their_column() {
+        for kind in EmailKind::ALL {
+            assert_eq!(EmailKind::from_str(kind.to_str()), Some(kind));
+        }
+        assert_eq!(EmailKind::from_str("unknown"), None);
+    }
+
+    #[test]
+    fn migrate_classifies_stored_emails() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE leads (email TEXT);
+                INSERT INTO leads (email) VALUES ('jane@acme.com'), ('sales@acme.com'), (NULL);",
+            )
+            .unwrap();
+        migrate(&connection).unwrap();
+        let kinds = connection
+            .prepare("SELECT emailKind FROM leads ORDER BY rowid")
+            .unwrap()
+            .query_map([], |row| row.get::<_, Option<String>>(0))
+            .unwrap()
+            .collect::<Result<Vec<Option<String>>, rusqlite::Error>>()
+            .unwrap();
+        assert_eq!(kinds, [Some("personal".to_string()), Some("role".to_string()), None]);
+        assert_eq!(reclassify(&connection).unwrap(), 0);
+    }
+}
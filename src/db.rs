@@ -1,26 +1,26 @@
 use std::{cmp::min, path::PathBuf};
-use anyhow::anyhow;
 use colorize::AnsiColor;
-use directories::BaseDirs;
 use inquire::{Select};
 use prettytable::Table;
 use rusqlite::{Connection, Row};
 
-use crate::{apikey::{ApiKey, ApiKeyFor}, clear_and_logo, list::List};
+use crate::{apikey::{ApiKey, ApiKeyFor}, clear_and_logo, error::{AppError, AppResult}, list::List, profile};
 
-pub fn open_connection() -> Connection {
-    Connection::open(sqlite_path()).unwrap()
+pub fn open_connection() -> Result<Connection, rusqlite::Error> {
+    let path = sqlite_path()
+        .ok_or_else(|| rusqlite::Error::InvalidPath(PathBuf::from("no home directory found")))?;
+    Connection::open(path)
 }
 
-pub fn sqlite_path() -> PathBuf {
-    BaseDirs::new()
-        .unwrap()
-        .data_local_dir()
-        .join("apollo/apollo.sqlite")
+/// the database of the active profile
+pub fn sqlite_path() -> Option<PathBuf> {
+    profile::database(&profile::active())
 }
 
 pub trait DBentity {
-    fn new(args: &Row) -> Self;
+    fn new(args: &Row) -> Result<Self, rusqlite::Error>
+    where
+        Self: Sized;
     fn table_name() -> &'static str;
     fn insert_new(&self) -> Result<usize, rusqlite::Error>;
     fn custom_query() -> Option<&'static str>;
@@ -53,13 +53,12 @@ fn get_total_and_update<T>(
     config: &mut PagedResult<T>,
     connection: &Connection,
     query: String,
-) -> () {
+) -> Result<(), rusqlite::Error> {
     //prepare the query
     let finalized_query = format!("SELECT COUNT(*) AS total_count FROM ({})", query);
-    let rows: u32 = connection
-        .query_row(&finalized_query, [], |row| row.get(0))
-        .unwrap();
+    let rows: u32 = connection.query_row(&finalized_query, [], |row| row.get(0))?;
     config.total_rows = rows;
+    Ok(())
 }
 
 /// list all records of an entity
@@ -71,7 +70,7 @@ pub fn list_all<T>(
 where
     T: DBentity,
 {
-    let connection = open_connection();
+    let connection = open_connection()?;
     let query_without_pagination = match custom_query {
         Some(query) => query.to_string(),
         _ => format!("SELECT * FROM {};", T::table_name()),
@@ -87,7 +86,7 @@ where
     //cleanup of query
     let cleaned = query_without_pagination.trim().trim_end_matches(';');
     //get total records and mutate
-    get_total_and_update(&mut resultant_page, &connection, cleaned.to_string());
+    get_total_and_update(&mut resultant_page, &connection, cleaned.to_string())?;
 
     let query = match page_config {
         Some(PageConfig { offset, rows }) => {
@@ -99,23 +98,20 @@ where
 
     let mut rows_result = connection.prepare(query.as_str())?;
 
-    let rows_iterator = rows_result.query_map([], |row| Ok(T::new(row)))?;
+    let rows_iterator = rows_result.query_map([], |row| T::new(row))?;
     let mut data = Vec::new();
     let mut rows = 0;
     for row in rows_iterator {
         rows += 1;
-        data.push(row.unwrap())
+        data.push(row?)
     }
-    //no pagination
-
     resultant_page.items = data;
-    if matches!(page_config, None) {
+
+    //no pagination
+    let Some(page_config_sure) = page_config else {
         resultant_page.rows = resultant_page.total_rows; //since all rows available are returned
         return Ok(resultant_page);
     };
-
-
-    let page_config_sure = page_config.unwrap();
     resultant_page.rows = rows;
     resultant_page.rows_left = resultant_page
         .total_rows
@@ -144,35 +140,38 @@ where
 }
 
 
-pub fn api_keys_available(purpose: ApiKeyFor) -> Option<Vec<ApiKey>> {
+/// all the API keys usable for a purpose
+pub fn api_keys_for(purpose: &ApiKeyFor) -> Result<Vec<ApiKey>, rusqlite::Error> {
         let api_keys_query = format!(
         "SELECT * FROM {} WHERE for = '{}' OR for = 'both';",
         ApiKey::table_name(),
         purpose.to_str()
     );
-    let apikeys = list_all::<ApiKey>(Some(api_keys_query), None)
-        .unwrap()
-        .items;
-    if apikeys.len() == 0 {
+    Ok(list_all::<ApiKey>(Some(api_keys_query), None)?.items)
+}
+
+pub fn api_keys_available(purpose: ApiKeyFor) -> AppResult<Option<Vec<ApiKey>>> {
+    let apikeys = api_keys_for(&purpose)?;
+    if apikeys.is_empty() {
         println!(
             "{}",
             "you don't have any API keys, create one to continue".red()
         );
-        return None;
+        return Ok(None);
     };
-    return Some(apikeys);
+    Ok(Some(apikeys))
 }
 
 pub enum ListSelectedResult {
     Back,
     NoLists,
-    ListSelected(List)
+    ListSelected(Box<List>)
 }
 
-pub fn list_selection() -> anyhow::Result<ListSelectedResult> {
+pub fn list_selection() -> AppResult<ListSelectedResult> {
     let all_lists = list_all::<List>(List::custom_query().map(|v| v.to_string()), None)?
         .items;
-    if all_lists.len() == 0 {
+    if all_lists.is_empty() {
         return Ok(ListSelectedResult::NoLists);
     };
 
@@ -181,9 +180,9 @@ pub fn list_selection() -> anyhow::Result<ListSelectedResult> {
     let mut all_options : Vec<String> = all_lists.iter().map(|list| list.name.clone()).collect();
     all_options.push("Back".to_string());
     let selected_list_name = Select::new("please select a list ", all_options).prompt()?;
-    if selected_list_name == "Go Back" {
+    if selected_list_name == "Back" {
         return  Ok(ListSelectedResult::Back);
     }
-    let selected_list = all_lists.iter().find(|list|{list.name == selected_list_name}).cloned().ok_or_else(||anyhow!("list not found error"))?;
-    return  Ok(ListSelectedResult::ListSelected(selected_list));
+    let selected_list = all_lists.iter().find(|list|{list.name == selected_list_name}).cloned().ok_or_else(||AppError::Other("list not found error".to_string()))?;
+    Ok(ListSelectedResult::ListSelected(Box::new(selected_list)))
 } 
\ No newline at end of file
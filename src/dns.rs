@@ -0,0 +1,366 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use simple_dns::{rdata::RData, Name, Packet, PacketFlag, Question, CLASS, RCODE, TYPE};
+
+use crate::{
+    config,
+    error::{AppError, AppResult},
+    jobs::lock,
+};
+
+/// a record found for a name
+pub enum Record {
+    Mx { preference: u16, exchange: String },
+    Address(IpAddr),
+}
+
+/// answers DNS queries, `UdpResolver` asks real servers and anything else can stand in for it
+pub trait Resolver: Send + Sync {
+    /// the MX, A or AAAA records of a name, none when the name doesn't exist
+    fn query(&self, name: &str, record: TYPE) -> AppResult<Vec<Record>>;
+}
+
+/// asks the servers of `dns.server`, or those of /etc/resolv.conf when it is empty, over UDP
+pub struct UdpResolver {
+    servers: Vec<SocketAddr>,
+    timeout: Duration,
+}
+
+impl UdpResolver {
+    pub fn from_settings() -> AppResult<Self> {
+        let server = config::get("dns.server");
+        let servers = match server.trim() {
+            "" => system_servers()?,
+            server => vec![parse_server(server)?],
+        };
+        Ok(Self {
+            servers,
+            timeout: Duration::from_millis(config::get_u64("dns.timeout_ms").max(100)),
+        })
+    }
+
+    fn ask(&self, server: SocketAddr, query: &[u8], id: u16) -> AppResult<Vec<u8>> {
+        let local: SocketAddr = match server {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        socket.send_to(query, server)?;
+        let mut buffer = [0; 4096];
+        loop {
+            let (len, from) = socket.recv_from(&mut buffer)?;
+            //ignore stray packets, the id makes sure this is our answer
+            if from == server && len >= 2 && u16::from_be_bytes([buffer[0], buffer[1]]) == id {
+                return Ok(buffer[..len].to_vec());
+            }
+        }
+    }
+
+    /// asks again over TCP, for answers too long for a UDP packet
+    fn ask_tcp(&self, server: SocketAddr, query: &[u8], id: u16) -> AppResult<Vec<u8>> {
+        let mut stream = TcpStream::connect_timeout(&server, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        //over TCP every message starts with its length
+        let mut message = (query.len() as u16).to_be_bytes().to_vec();
+        message.extend_from_slice(query);
+        stream.write_all(&message)?;
+        let mut len = [0; 2];
+        stream.read_exact(&mut len)?;
+        let mut answer = vec![0; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut answer)?;
+        if answer.len() < 2 || u16::from_be_bytes([answer[0], answer[1]]) != id {
+            return Err(AppError::Network(format!("DNS server {} answered another query over TCP", server)));
+        }
+        Ok(answer)
+    }
+}
+
+impl Resolver for UdpResolver {
+    fn query(&self, name: &str, record: TYPE) -> AppResult<Vec<Record>> {
+        let id = fastrand::u16(..);
+        let mut packet = Packet::new_query(id);
+        packet.set_flags(PacketFlag::RECURSION_DESIRED);
+        let qname = Name::new(name).map_err(|e| AppError::Parse(format!("the domain {}: {}", name, e)))?;
+        packet.questions.push(Question::new(qname, record.into(), CLASS::IN.into(), false));
+        let query = packet
+            .build_bytes_vec()
+            .map_err(|e| AppError::Other(format!("couldn't build the DNS query for {}: {}", name, e)))?;
+
+        let mut last_error = AppError::Network("no DNS server to ask".to_string());
+        for server in self.servers.iter() {
+            let mut answer = self.ask(*server, &query, id);
+            //a truncated answer is missing records, TCP has room for all of them
+            if let Ok(udp) = &answer
+                && Packet::parse(udp).is_ok_and(|packet| packet.has_flags(PacketFlag::TRUNCATION))
+            {
+                log::debug!("dns truncated name={} server={}, asking over tcp", name, server);
+                answer = self.ask_tcp(*server, &query, id);
+            }
+            let answer = match answer {
+                Ok(answer) => answer,
+                Err(e) => {
+                    log::warn!("dns query name={} server={} error=\"{}\"", name, server, e);
+                    last_error = AppError::Network(format!("DNS server {}: {}", server, e));
+                    continue;
+                }
+            };
+            let answer = Packet::parse(&answer)
+                .map_err(|e| AppError::Parse(format!("the DNS answer from {}: {}", server, e)))?;
+            return match answer.rcode() {
+                RCODE::NoError => Ok(answer
+                    .answers
+                    .iter()
+                    .filter_map(|answer| match &answer.rdata {
+                        RData::MX(mx) => Some(Record::Mx {
+                            preference: mx.preference,
+                            exchange: mx.exchange.to_string(),
+                        }),
+                        RData::A(a) => Some(Record::Address(IpAddr::from(Ipv4Addr::from(a.address)))),
+                        RData::AAAA(aaaa) => Some(Record::Address(IpAddr::from(Ipv6Addr::from(aaaa.address)))),
+                        _ => None,
+                    })
+                    .collect()),
+                RCODE::NameError => Ok(Vec::new()),
+                rcode => Err(AppError::Network(format!("DNS server {} answered {:?} for {}", server, rcode, name))),
+            };
+        }
+        Err(last_error)
+    }
+}
+
+/// `1.1.1.1`, `127.0.0.1:5353` or `[::1]:53`
+fn parse_server(server: &str) -> AppResult<SocketAddr> {
+    if let Ok(address) = server.parse::<SocketAddr>() {
+        return Ok(address);
+    }
+    server
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| AppError::Parse(format!("dns.server {}, expected an IP address with an optional port", server)))
+}
+
+fn system_servers() -> AppResult<Vec<SocketAddr>> {
+    let text = fs::read("/etc/resolv.conf")
+        .map_err(|e| AppError::Other(format!("couldn't read /etc/resolv.conf, set dns.server instead: {}", e)))?;
+    let config = resolv_conf::Config::parse(text)
+        .map_err(|e| AppError::Parse(format!("/etc/resolv.conf: {}", e)))?;
+    Ok(config
+        .nameservers
+        .into_iter()
+        .map(|ip| SocketAddr::new(IpAddr::from(ip), 53))
+        .collect())
+}
+
+/// the mail servers of a domain, most preferred first
+/// the domain itself when it has no MX record (RFC 5321), none for the null MX (RFC 7505)
+pub fn exchangers(resolver: &dyn Resolver, domain: &str) -> AppResult<Vec<String>> {
+    let mut exchangers = resolver
+        .query(domain, TYPE::MX)?
+        .into_iter()
+        .filter_map(|record| match record {
+            Record::Mx { preference, exchange } => Some((preference, exchange)),
+            Record::Address(_) => None,
+        })
+        .collect::<Vec<(u16, String)>>();
+    if exchangers.is_empty() {
+        return Ok(vec![domain.to_string()]);
+    }
+    exchangers.sort();
+    Ok(exchangers
+        .into_iter()
+        .map(|(_, exchange)| exchange.trim_end_matches('.').to_string())
+        .filter(|exchange| !exchange.is_empty())
+        .collect())
+}
+
+/// whether a domain can receive mail, cached for `dns.cache_secs`
+static CACHE: LazyLock<Mutex<HashMap<String, (bool, Instant)>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// a domain receives mail when it has an MX record other than the null MX (RFC 7505),
+/// or, without any MX record, an address to fall back to (RFC 5321)
+pub fn accepts_mail(resolver: &dyn Resolver, domain: &str) -> AppResult<bool> {
+    accepts_mail_within(resolver, domain, Duration::from_secs(config::get_u64("dns.cache_secs")))
+}
+
+/// a failed lookup returns before the cache is written, so it is asked again next time
+fn accepts_mail_within(resolver: &dyn Resolver, domain: &str, ttl: Duration) -> AppResult<bool> {
+    if let Some((accepts, checked)) = lock(&CACHE).get(domain)
+        && checked.elapsed() < ttl
+    {
+        return Ok(*accepts);
+    }
+
+    let exchangers = resolver.query(domain, TYPE::MX)?;
+    let best = exchangers
+        .iter()
+        .filter_map(|record| match record {
+            Record::Mx { preference, exchange } => Some((preference, exchange.clone())),
+            Record::Address(_) => None,
+        })
+        .min();
+    let (accepts, target) = match (best, exchangers.len()) {
+        (None, _) => match address(resolver, domain)? {
+            Some(ip) => (true, format!("address {}", ip)),
+            None => (false, "none".to_string()),
+        },
+        (Some((_, exchange)), 1) if exchange.is_empty() || exchange == "." => (false, "null mx".to_string()),
+        (Some((_, exchange)), _) => (true, exchange),
+    };
+    log::info!("mx precheck domain={} accepts_mail={} mx={}", domain, accepts, target);
+    lock(&CACHE).insert(domain.to_string(), (accepts, Instant::now()));
+    Ok(accepts)
+}
+
+/// the IPv4 address of a domain, or its IPv6 one when it has none
+fn address(resolver: &dyn Resolver, domain: &str) -> AppResult<Option<IpAddr>> {
+    for record in [TYPE::A, TYPE::AAAA] {
+        if let Some(Record::Address(ip)) = resolver.query(domain, record)?.into_iter().next() {
+            return Ok(Some(ip));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use simple_dns::{rdata::MX, ResourceRecord};
+
+    use super::*;
+
+    /// answers from fixed records, `mx: None` fails the MX lookup like an unreachable DNS server
+    struct FakeResolver {
+        mx: Option<Vec<(u16, &'static str)>>,
+        address: Option<IpAddr>,
+    }
+
+    impl Resolver for FakeResolver {
+        fn query(&self, name: &str, record: TYPE) -> AppResult<Vec<Record>> {
+            match record {
+                TYPE::MX => match &self.mx {
+                    Some(mx) => Ok(mx
+                        .iter()
+                        .map(|(preference, exchange)| Record::Mx {
+                            preference: *preference,
+                            exchange: exchange.to_string(),
+                        })
+                        .collect()),
+                    None => Err(AppError::Network(format!("no DNS server answered for {}", name))),
+                },
+                _ => Ok(self
+                    .address
+                    .filter(|ip| ip.is_ipv4() == (record == TYPE::A))
+                    .map(Record::Address)
+                    .into_iter()
+                    .collect()),
+            }
+        }
+    }
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn null_mx_is_rejected() {
+        let resolver = FakeResolver { mx: Some(vec![(0, ".")]), address: Some(Ipv4Addr::LOCALHOST.into()) };
+        assert!(!accepts_mail_within(&resolver, "null-mx.test", TTL).unwrap());
+        assert!(exchangers(&resolver, "null-mx.test").unwrap().is_empty());
+    }
+
+    #[test]
+    fn no_mx_with_an_address_is_accepted() {
+        let resolver = FakeResolver { mx: Some(Vec::new()), address: Some(Ipv4Addr::LOCALHOST.into()) };
+        assert!(accepts_mail_within(&resolver, "a-only.test", TTL).unwrap());
+        assert_eq!(exchangers(&resolver, "a-only.test").unwrap(), vec!["a-only.test"]);
+    }
+
+    #[test]
+    fn nxdomain_is_rejected() {
+        //the UDP resolver answers a name error with no records
+        let resolver = FakeResolver { mx: Some(Vec::new()), address: None };
+        assert!(!accepts_mail_within(&resolver, "nxdomain.test", TTL).unwrap());
+    }
+
+    #[test]
+    fn exchangers_are_sorted_by_preference() {
+        let resolver = FakeResolver { mx: Some(vec![(20, "backup.mx.test."), (10, "mx.test.")]), address: None };
+        assert_eq!(exchangers(&resolver, "sorted.test").unwrap(), vec!["mx.test", "backup.mx.test"]);
+    }
+
+    #[test]
+    fn failed_lookup_is_not_cached() {
+        let failing = FakeResolver { mx: None, address: None };
+        assert!(accepts_mail_within(&failing, "flaky.test", TTL).is_err());
+        assert!(!lock(&CACHE).contains_key("flaky.test"));
+        let working = FakeResolver { mx: Some(vec![(10, "mx.flaky.test")]), address: None };
+        assert!(accepts_mail_within(&working, "flaky.test", TTL).unwrap());
+    }
+
+    #[test]
+    fn no_mx_with_only_an_ipv6_address_is_accepted() {
+        let resolver = FakeResolver { mx: Some(Vec::new()), address: Some(Ipv6Addr::LOCALHOST.into()) };
+        assert!(accepts_mail_within(&resolver, "aaaa-only.test", TTL).unwrap());
+    }
+
+    /// a DNS server on 127.0.0.1 answering MX queries with `exchange`,
+    /// over UDP only with the truncated flag when `truncate` is set, and over TCP in full
+    fn stand_in(exchange: &'static str, truncate: bool) -> SocketAddr {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = udp.local_addr().unwrap();
+        let tcp = TcpListener::bind(address).unwrap();
+        let reply = move |query: &[u8], truncated: bool| {
+            let query = Packet::parse(query).unwrap();
+            let mut reply = Packet::new_reply(query.id());
+            if truncated {
+                reply.set_flags(PacketFlag::TRUNCATION);
+            } else {
+                let qname = query.questions[0].qname.clone();
+                let mx = MX { preference: 10, exchange: Name::new_unchecked(exchange) };
+                reply.answers.push(ResourceRecord::new(qname, CLASS::IN, 60, RData::MX(mx)));
+            }
+            reply.questions = query.questions;
+            reply.build_bytes_vec().unwrap()
+        };
+        thread::spawn(move || {
+            let mut buffer = [0; 512];
+            let (len, from) = udp.recv_from(&mut buffer).unwrap();
+            udp.send_to(&reply(&buffer[..len], truncate), from).unwrap();
+        });
+        thread::spawn(move || {
+            let (mut stream, _) = tcp.accept().unwrap();
+            let mut len = [0; 2];
+            stream.read_exact(&mut len).unwrap();
+            let mut query = vec![0; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut query).unwrap();
+            let answer = reply(&query, false);
+            stream.write_all(&(answer.len() as u16).to_be_bytes()).unwrap();
+            stream.write_all(&answer).unwrap();
+        });
+        address
+    }
+
+    fn exchanges_from(server: SocketAddr) -> Vec<String> {
+        let resolver = UdpResolver { servers: vec![server], timeout: Duration::from_secs(2) };
+        exchangers(&resolver, "example.com").unwrap()
+    }
+
+    #[test]
+    fn udp_resolver_reads_the_answer() {
+        assert_eq!(exchanges_from(stand_in("mx.example.com", false)), vec!["mx.example.com"]);
+    }
+
+    #[test]
+    fn truncated_answer_is_asked_again_over_tcp() {
+        //parsed as complete, the empty truncated answer would fall back to the domain itself
+        assert_eq!(exchanges_from(stand_in("mx.example.com", true)), vec!["mx.example.com"]);
+    }
+}
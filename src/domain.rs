@@ -0,0 +1,143 @@
+use std::sync::LazyLock;
+
+use publicsuffix::{List, Psl};
+use rusqlite::Connection;
+use url::{Host, Url};
+
+use crate::{db::DBentity, leads::Lead, organizations::Organization};
+
+/// the public suffix list, from https://publicsuffix.org/list/public_suffix_list.dat
+static SUFFIXES: LazyLock<List> = LazyLock::new(|| {
+    include_str!("data/public_suffix_list.dat")
+        .parse()
+        .unwrap_or_default()
+});
+
+/// the domain emails of a company are sent to, from whatever was typed as its website
+/// `http://www.Acme.com:443/about`, `m.acme.com` and `acme.com.` all become `acme.com`,
+/// `shop.acme.co.uk` becomes `acme.co.uk` and IDNs are kept in punycode
+pub fn normalize(website: &str) -> Option<String> {
+    let website = website.trim();
+    if website.is_empty() {
+        return None;
+    }
+    //without a scheme the url parser takes the domain for a path
+    let url = match website.contains("://") {
+        true => Url::parse(website),
+        false => Url::parse(&format!("http://{}", website)),
+    }
+    .ok()?;
+    //the parser lowercases the host and converts IDNs to punycode
+    let Some(Host::Domain(host)) = url.host() else {
+        return None;
+    };
+    registrable(host.trim_end_matches('.'))
+}
+
+/// the part of a host that can be registered, dropping subdomains like `www` or `m`
+fn registrable(host: &str) -> Option<String> {
+    let domain = SUFFIXES.domain(host.as_bytes())?;
+    //an unlisted suffix is taken as a top level domain, which needs at least two labels
+    let domain = std::str::from_utf8(domain.as_bytes()).ok()?;
+    domain.contains('.').then(|| domain.to_string())
+}
+
+/// the normalized domain of an email address
+pub fn of_email(email: &str) -> Option<String> {
+    let (_, domain) = email.trim().rsplit_once('@')?;
+    normalize(domain)
+}
+
+/// migration storing the normalized domain of every lead, companies keyed by a domain
+/// that normalizes to one already known are merged into it
+pub fn migrate(connection: &Connection) -> Result<(), rusqlite::Error> {
+    let leads = Lead::table_name();
+    let organizations = Organization::table_name();
+    connection.execute_batch(&format!("ALTER TABLE {} ADD COLUMN domain TEXT;", leads))?;
+    let websites = connection
+        .prepare(&format!("SELECT rowid, org_website FROM {} WHERE org_website IS NOT NULL", leads))?
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<(i64, String)>, rusqlite::Error>>()?;
+    for (rowid, website) in websites {
+        connection.execute(
+            &format!("UPDATE {} SET domain = ?1 WHERE rowid = ?2", leads),
+            (normalize(&website), rowid),
+        )?;
+    }
+
+    let companies = connection
+        .prepare(&format!("SELECT id, domain, website FROM {} ORDER BY id", organizations))?
+        .query_map([], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+        })?
+        .collect::<Result<Vec<(u32, String, Option<String>)>, rusqlite::Error>>()?;
+    for (id, old, website) in companies {
+        let Some(new) = normalize(website.as_deref().unwrap_or(&old)) else {
+            continue;
+        };
+        if new == old {
+            continue;
+        }
+        let existing: Option<u32> = connection
+            .query_row(&format!("SELECT id FROM {} WHERE domain = ?1", organizations), [&new], |row| row.get(0))
+            .ok();
+        match existing {
+            None => {
+                connection.execute(&format!("UPDATE {} SET domain = ?1 WHERE id = ?2", organizations), (&new, id))?;
+            }
+            Some(into) => {
+                connection.execute(&format!("UPDATE {} SET orgId = ?1 WHERE orgId = ?2", leads), (into, id))?;
+                connection.execute(
+                    &format!(
+                        "UPDATE {0} SET emailPattern = COALESCE(emailPattern, (SELECT emailPattern FROM {0} WHERE id = ?2)), \
+                         notes = NULLIF(TRIM(COALESCE(notes, '') || char(10) || COALESCE((SELECT notes FROM {0} WHERE id = ?2), ''), char(10)), '') \
+                         WHERE id = ?1",
+                        organizations
+                    ),
+                    (into, id),
+                )?;
+                connection.execute(&format!("DELETE FROM {} WHERE id = ?1", organizations), [id])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_drops_scheme_port_path_and_subdomains() {
+        assert_eq!(normalize("http://www.Acme.com:443/about").as_deref(), Some("acme.com"));
+        assert_eq!(normalize("m.acme.com").as_deref(), Some("acme.com"));
+        assert_eq!(normalize("  https://acme.com/  ").as_deref(), Some("acme.com"));
+    }
+
+    #[test]
+    fn normalize_keeps_multi_label_suffixes() {
+        assert_eq!(normalize("shop.acme.co.uk").as_deref(), Some("acme.co.uk"));
+        assert_eq!(normalize("https://www.acme.com.au").as_deref(), Some("acme.com.au"));
+        assert_eq!(normalize("co.uk"), None);
+    }
+
+    #[test]
+    fn normalize_converts_idns_to_punycode() {
+        assert_eq!(normalize("www.bücher.de").as_deref(), Some("xn--bcher-kva.de"));
+        assert_eq!(normalize("https://xn--bcher-kva.de/shop").as_deref(), Some("xn--bcher-kva.de"));
+    }
+
+    #[test]
+    fn normalize_takes_bare_hosts() {
+        assert_eq!(normalize("acme.com.").as_deref(), Some("acme.com"));
+        assert_eq!(normalize("localhost"), None);
+        assert_eq!(normalize("127.0.0.1"), None);
+        assert_eq!(normalize(""), None);
+    }
+
+    #[test]
+    fn of_email_normalizes_the_domain() {
+        assert_eq!(of_email("Jane@Mail.Acme.co.uk").as_deref(), Some("acme.co.uk"));
+        assert_eq!(of_email("not an email"), None);
+    }
+}
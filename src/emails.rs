@@ -1,40 +1,44 @@
 use crate::{
-    apikey::{ApiKeyFor, ApiKeyRotation}, db::{api_keys_available, list_all, list_selection, DBentity, ListSelectedResult, PageConfig}, empty_validator, leads::{header_constructor, num_validator, Lead}, list::List, MenuState
+    apikey::{ApiKey, ApiKeyFor}, backup, classify, config, dns::{self, Resolver, UdpResolver}, domain, error::{AppError, AppResult}, http::{ProviderClient, EMAIL_PROVIDER}, jobs::{self, ListLock, Reporter}, smtp::SmtpVerifier, traffic::{self, Traffic}, progress::{Progress, ProgressKind}, db::{api_keys_available, list_all, list_selection, DBentity, ListSelectedResult, PageConfig}, empty_validator, leads::{num_validator, EmailConfidence, Lead}, list::List, organizations::{self, Organization}, MenuState
 };
-use anyhow::anyhow;
 use colorize::AnsiColor;
+use rusqlite::Connection;
 use inquire::{Text, validator::Validation};
-use regex::Regex;
-use reqwest::blocking::Client;
 use serde::Deserialize;
 use url::Url;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, MutexGuard,
+    },
+    thread,
+};
 
-pub fn fetch_emails() -> MenuState {
-    let selection_maybe = list_selection();
-    let selected_list_maybe: Option<List>;
-    if let Err(e) = selection_maybe {
-        println!("something went wrong {}", e);
-        return MenuState::GoodBye;
-    }
-    let selection = selection_maybe.unwrap();
-    match selection {
-        ListSelectedResult::Back => {return MenuState::Fetch},
-        ListSelectedResult::NoLists => {return MenuState::Lists},
-        ListSelectedResult::ListSelected(list) => {selected_list_maybe = Some(list)}
+pub fn fetch_emails() -> AppResult<MenuState> {
+    let mut selected_list: List = match list_selection()? {
+        ListSelectedResult::Back => return Ok(MenuState::Fetch),
+        ListSelectedResult::NoLists => return Ok(MenuState::Lists),
+        ListSelectedResult::ListSelected(list) => *list,
+    };
+    let list_lock = match ListLock::acquire(&selected_list) {
+        Ok(list_lock) => list_lock,
+        Err(e) => {
+            println!("{}", e.to_string().red());
+            return Ok(MenuState::Fetch);
+        }
     };
 
     //how many emails to fetch
-    let mut selected_list = selected_list_maybe.unwrap();
     if selected_list.leads_fetched == 0 {
         println!(
             "{}",
             "you do not have any leads to fetch emails of, first fetch some leads.".red()
         );
-        return MenuState::Fetch;
+        return Ok(MenuState::Fetch);
     };
     if selected_list.leads_fetched == selected_list.emails_fetched {
         println!("{}", "all leads already have emails checked.".red());
-        return MenuState::Main;
+        return Ok(MenuState::Main);
     };
     //ask how many emails to fetch
     let emails_count = Text::new("how many emails do you want to fetch ?")
@@ -42,183 +46,430 @@ pub fn fetch_emails() -> MenuState {
             Box::new(empty_validator),
             Box::new(num_validator),
             Box::new(move |v: &str| {
-                let to_fetch: u32 = v.parse::<u32>().unwrap();
+                //num_validator already rejected anything else
+                let to_fetch: u32 = v.trim().parse::<u32>().unwrap_or_default();
                 let max_possible = selected_list.leads_fetched - selected_list.emails_fetched;
                 if to_fetch > (max_possible) {
-                    return Ok(Validation::Invalid(
+                    Ok(Validation::Invalid(
                         format!(
                             "the maximum number of emails that can be fetched is {}",
                             max_possible
                         )
                         .into(),
-                    ));
+                    ))
                 } else {
-                    return Ok(Validation::Valid);
+                    Ok(Validation::Valid)
                 }
             }),
         ])
-        .prompt()
-        .unwrap();
-
-    let client = Client::builder()
-        .danger_accept_invalid_certs(true)
-        .build()
-        .unwrap();
+        .prompt()?
+        .trim()
+        .parse::<u32>()
+        .map_err(|e| AppError::Parse(format!("the number of emails: {}", e)))?;
 
-    let maybe_api_keys = api_keys_available(ApiKeyFor::Email);
-    if matches!(maybe_api_keys, None) {
-        return MenuState::APIkeys;
+    let api_keys = match uses_api() {
+        false => Vec::new(),
+        true => match api_keys_available(ApiKeyFor::Email)? {
+            Some(api_keys) => api_keys,
+            None => return Ok(MenuState::APIkeys),
+        },
     };
-    let apikeys = maybe_api_keys.unwrap();
+    run_emails_job(&mut selected_list, list_lock, emails_count, api_keys, &Reporter::Console);
 
-    let mut api_config = ApiKeyRotation {
-        active_index: 0,
-        api_keys_avaialble: apikeys,
-    };
+    Ok(MenuState::Fetch)
+}
+
+/// checks whether an address can receive mail, `verify.backend` picks the implementation
+pub trait Verifier: Sync {
+    /// the status of the address when it can receive email, `valid` or `accept_all`
+    fn check(&self, email: &str) -> AppResult<Option<String>>;
+    /// progress of the job, counting the checks sent
+    fn progress(&self) -> MutexGuard<'_, Progress>;
+    fn reporter(&self) -> &Reporter;
+}
+
+/// an address found for a lead
+struct Found {
+    email: String,
+    /// what the verifier answered, `valid` or `accept_all`
+    status: String,
+    confidence: EmailConfidence,
+}
+
+/// whether emails are verified through the Validect API, which needs API keys
+pub fn uses_api() -> bool {
+    config::get("verify.backend") != "smtp"
+}
+
+/// finds emails for the next `count` leads of the list, shared by the prompt based menus and the TUI
+/// leads are checked by a pool of `emails.workers` threads, this thread is the only one writing to the database
+/// the list stays locked until the job is done
+pub fn run_emails_job(selected_list: &mut List, _list_lock: ListLock, count: u32, apikeys: Vec<ApiKey>, reporter: &Reporter) {
+    let _running = jobs::Running::start();
+    if jobs::dry_run() {
+        plan_emails_job(selected_list, count, &apikeys, reporter);
+        return;
+    }
+    backup::before_job(reporter);
     //find the leads that need thier emails fetched
-    let leads = list_all::<Lead>(
-        Some(format!("SELECT * FROM {} WHERE listId = {}",Lead::table_name(),&selected_list.id)),
+    let leads = match list_all::<Lead>(
+        Some(format!("SELECT * FROM {} WHERE listId = {} ORDER BY rowid",Lead::table_name(),&selected_list.id)),
         Some(PageConfig {
-            rows: emails_count.parse().unwrap(),
+            rows: count,
             offset: selected_list.emails_fetched,
         }),
-    )
-    .unwrap()
-    .items;
-
-    for lead in leads {
-        let website_maybe = lead.org_website;
-        if matches!(website_maybe, None) {
-            if let Err(e) = increment_list_emails_count(&mut selected_list) {
-                println!("couldn't update list meta for emails fetched {}", e);
-                break;
-            }
-            continue;
+    ) {
+        Ok(page) => page.items,
+        Err(e) => {
+            reporter.error(format!("couldn't load the leads: {}", e));
+            reporter.finished();
+            return;
         }
-        let website = website_maybe.unwrap();
-        let filter = Regex::new(r"^https?://(www\.)?").unwrap();
-        let email_result = find_email(
-            lead.first_name.to_lowercase(),
-            lead.last_name.to_lowercase(),
-            filter.replace(&website, "").to_string(),
-            &mut api_config,
-            &client,
-        );
-        match email_result {
+    };
+
+    //recordings only hold provider responses, a replay must not reach mail servers or DNS
+    let replaying = matches!(traffic::mode(), Traffic::Replay(_));
+    if replaying && !uses_api() {
+        reporter.error("the smtp backend asks mail servers directly and can't be replayed, set verify.backend to validect");
+        reporter.finished();
+        return;
+    }
+    let progress = Progress::new(ProgressKind::Emails, leads.len() as u32);
+    reporter.progress(&progress);
+    let verifier: AppResult<Box<dyn Verifier>> = match uses_api() {
+        true => ProviderClient::new(&EMAIL_PROVIDER, apikeys, progress, reporter)
+            .map(|provider| Box::new(provider) as Box<dyn Verifier>),
+        false => SmtpVerifier::from_settings(progress, reporter).map(|smtp| Box::new(smtp) as Box<dyn Verifier>),
+    };
+    let provider = match verifier {
+        Ok(verifier) => verifier,
+        Err(e) => {
+            reporter.error(format!("couldn't set up the email verification: {}", e));
+            reporter.finished();
+            return;
+        }
+    };
+    let workers = (config::get_u64("emails.workers").max(1) as usize).min(leads.len().max(1));
+    //without a resolver every lead is verified, as before the pre-check existed
+    let resolver = match config::get_bool("dns.precheck") && !replaying {
+        false => None,
+        true => match UdpResolver::from_settings() {
+            Ok(resolver) => Some(resolver),
             Err(e) => {
-                println!("something went wrong while finding email: {}", e);
-                break;
-                //DONT update the list meta, this one is not fetched
+                reporter.error(format!("couldn't set up the MX pre-check, verifying every lead: {}", e));
+                None
+            }
+        },
+    };
+
+    if (resolver.is_some() || !uses_api()) && !config::get("proxy.url").trim().is_empty() {
+        reporter.error("DNS lookups and SMTP verification don't go through proxy.url, they connect directly");
+    }
+
+    let next_lead = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel::<(usize, AppResult<Option<Found>>)>();
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let (leads, next_lead, stop, provider) = (&leads, &next_lead, &stop, provider.as_ref());
+            let resolver = resolver.as_ref().map(|resolver| resolver as &dyn Resolver);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let index = next_lead.fetch_add(1, Ordering::Relaxed);
+                    let Some(lead) = leads.get(index) else {
+                        break;
+                    };
+                    let result = match lead.org_website {
+                        None => Ok(None),
+                        Some(_) => find_email(lead, provider, resolver),
+                    };
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        //emailsFetched is an offset into the list, so it only moves over leads checked without a gap
+        let mut checked = vec![false; leads.len()];
+        let mut checked_prefix = 0;
+        //after a stop the workers finish the lead they are on, those results are still saved
+        for (index, result) in receiver {
+            match result {
+                //the error that stopped the job was already shown
+                Err(e) if stop.load(Ordering::Relaxed) => {
+                    log::warn!("email lead={} error=\"{}\"", leads[index].id, e);
+                    continue;
+                }
+                Err(e) => {
+                    reporter.error(format!("something went wrong while finding email: {}", e));
+                    //DONT update the list meta, this one is not fetched
+                    stop.store(true, Ordering::Relaxed);
+                    continue;
+                }
+                Ok(Some(found)) => {
+                    let update_result = Lead::update_email(leads[index].id.clone(), selected_list.id, found.email.clone(), found.confidence);
+                    if let Err(e) = update_result {
+                        reporter.error(format!("couldn't update email for a lead {}", e));
+                        stop.store(true, Ordering::Relaxed);
+                        continue;
+                    }
+                    if let Err(e) = organizations::learn(&leads[index], &found.email, &found.status, found.confidence) {
+                        reporter.error(format!("couldn't note the email on its company {}", e));
+                    }
+                    reporter.info(format!("email {} {}", found.confidence.to_str(), found.email));
+                    provider.progress().found += 1;
+                }
+                Ok(None) => {}
+            }
+            checked[index] = true;
+            let previous_prefix = checked_prefix;
+            while checked_prefix < checked.len() && checked[checked_prefix] {
+                checked_prefix += 1;
             }
-            Ok(Some(email_confirmed)) => {
-                let update_result = Lead::update_email(lead.id, email_confirmed.clone());
-                println!("email confirmed {}", email_confirmed);
-                if let Err(e) = update_result {
-                    println!("couldn't update email for a lead {}", e);
-                    break;
+            if checked_prefix > previous_prefix {
+                let emails_fetched = selected_list.emails_fetched + (checked_prefix - previous_prefix) as u32;
+                if let Err(e) = selected_list.update_meta(None, None, Some(emails_fetched)) {
+                    reporter.error(format!("couldn't update list meta for emails fetched {}", e));
+                    stop.store(true, Ordering::Relaxed);
+                    continue;
                 }
             }
-            _ => {}
+            let mut progress = provider.progress();
+            progress.done += 1;
+            reporter.progress(&progress);
         }
+    });
+    reporter.finished();
+}
 
-        if let Err(e) = increment_list_emails_count(&mut selected_list) {
-            println!("couldn't update list meta for emails fetched {}", e);
-            break;
+/// describes what `run_emails_job` would send, without sending anything or touching the database
+fn plan_emails_job(selected_list: &List, count: u32, apikeys: &[ApiKey], reporter: &Reporter) {
+    reporter.info("dry run, no request is sent and nothing is saved");
+    let leads = match list_all::<Lead>(
+        Some(format!("SELECT * FROM {} WHERE listId = {} ORDER BY rowid",Lead::table_name(),&selected_list.id)),
+        Some(PageConfig {
+            rows: count,
+            offset: selected_list.emails_fetched,
+        }),
+    ) {
+        Ok(page) => page.items,
+        Err(e) => {
+            reporter.error(format!("couldn't load the leads: {}", e));
+            reporter.finished();
+            return;
         }
+    };
+
+    let mut checked = 0;
+    for lead in leads.iter() {
+        let candidates = candidate_addresses(lead);
+        if candidates.is_empty() {
+            reporter.info(format!("{}: skipped, no usable website", lead.label()));
+            continue;
+        }
+        if let Some(domain) = lead.org_website.as_deref().and_then(domain::normalize)
+            && let Some(kind) = classify::domain_kind(&domain)
+        {
+            reporter.info(format!("{}: skipped, {} is a {} provider", lead.label(), domain, kind.to_str()));
+            continue;
+        }
+        if let Some(domain) = lead.org_website.as_deref().and_then(domain::normalize)
+            && organizations::catch_all(&domain) == Some(true)
+        {
+            reporter.info(format!("{}: {}, {} accepts any address, not checked again", lead.label(), candidates[0], domain));
+            continue;
+        }
+        checked += 1;
+        reporter.info(format!("{}: {}", lead.label(), candidates.join(", ")));
+    }
+    if !uses_api() {
+        let server = match config::get("smtp.server").trim() {
+            "" => format!("the mail servers in the MX records of each domain, port {}", config::get_u64("smtp.port")),
+            server => server.to_string(),
+        };
+        reporter.info(format!("addresses are checked with RCPT TO at {}, no message is sent", server));
+    } else {
+        if let Some(email) = leads.iter().find_map(|lead| candidate_addresses(lead).into_iter().next())
+            && let Ok(url) = verify_url(&email)
+        {
+            reporter.info(format!("first request: GET {}", url));
+        }
+        let keys = apikeys.iter().map(|key| key.masked()).collect::<Vec<String>>();
+        reporter.info(format!("api key: {}, rotating through {} once its quota is used up", keys[0], keys.join(", ")));
+    }
+    if config::get_bool("dns.precheck") {
+        reporter.info("the MX records of each domain are looked up first, domains that can't receive mail are skipped");
     }
+    reporter.info(format!(
+        "{} leads, {} to check: between {} and {} requests, stopping at the first valid address of each lead, {} at a time",
+        leads.len(),
+        checked,
+        checked,
+        checked * 3,
+        config::get_u64("emails.workers").max(1)
+    ));
+    reporter.finished();
+}
+
+/// addresses tried for a lead, in order, until one is valid
+/// the pattern already confirmed at the lead's company is tried first
+fn candidate_addresses(lead: &Lead) -> Vec<String> {
+    let Some(domain) = lead.org_website.as_deref().and_then(domain::normalize) else {
+        return Vec::new();
+    };
+    let mut patterns = Vec::from(organizations::PATTERNS);
+    if let Some(learned) = organizations::pattern_for(&domain)
+        && let Some(position) = patterns.iter().position(|pattern| *pattern == learned)
+    {
+        patterns.remove(position);
+        patterns.insert(0, organizations::PATTERNS[position]);
+    }
+    patterns
+        .into_iter()
+        .map(|pattern| organizations::apply(pattern, lead))
+        .filter(|local_part| valid_local_part(local_part))
+        .map(|local_part| format!("{}@{}", local_part, domain))
+        .collect()
+}
 
-    MenuState::GoodBye
+/// an RFC 5321 dot-atom, names with other characters, accents or line breaks give no address to try
+fn valid_local_part(local_part: &str) -> bool {
+    !local_part.is_empty()
+        && local_part.len() <= 64
+        && local_part.split('.').all(|atom| {
+            !atom.is_empty()
+                && atom
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c))
+        })
 }
 
-fn increment_list_emails_count(list: &mut List) -> anyhow::Result<()> {
-    list.emails_fetched = list.emails_fetched + 1;
-    list.update_meta(None, None, Some(list.emails_fetched))
+fn verify_url(email: &str) -> AppResult<Url> {
+    let mut request_url = Url::parse(format!("https://{}/v1/verify", EMAIL_PROVIDER.host()).as_str())?;
+    request_url.query_pairs_mut().append_pair("email", email);
+    Ok(request_url)
 }
 
-fn find_email(
-    first_name: String,
-    last_name: String,
-    domain: String,
-    api_config: &mut ApiKeyRotation,
-    client: &Client,
-) -> anyhow::Result<Option<String>> {
-    let potential_addresses = Vec::from([
-        format!("{}@{}", last_name, domain),
-        format!("{}@{}", first_name, domain),
-        format!("{}{}@{}", &first_name[..=0], last_name, domain),
-    ]);
-
-    for canditate_address in potential_addresses {
-        let is_valid = single_address_validity_check(
-            canditate_address.as_str(),
-            api_config,
-            client,
-        );
+/// the first valid address of a lead, with the status the provider gave it and how sure it is
+fn find_email(lead: &Lead, provider: &dyn Verifier, resolver: Option<&dyn Resolver>) -> AppResult<Option<Found>> {
+    let Some(domain) = lead.org_website.as_deref().and_then(domain::normalize) else {
+        return Ok(None);
+    };
+    //a website on gmail.com says nothing about where the lead's work email is
+    if let Some(kind) = classify::domain_kind(&domain) {
+        provider.reporter().info(format!("{}: skipped, {} is a {} provider", lead.label(), domain, kind.to_str()));
+        return Ok(None);
+    }
+    //a server accepting any address would accept this one too, asking again only spends a request
+    if organizations::catch_all(&domain) == Some(true) {
+        provider.reporter().info(format!("{}: {} accepts any address, not checked again", lead.label(), domain));
+        return Ok(candidate_addresses(lead).into_iter().next().map(|email| Found {
+            email,
+            status: "accept_all".to_string(),
+            confidence: catch_all_confidence(&domain),
+        }));
+    }
+    //no verification request is worth sending to a domain without mail exchangers
+    if let Some(resolver) = resolver {
+        match dns::accepts_mail(resolver, &domain) {
+            Ok(true) => {}
+            Ok(false) => {
+                provider.reporter().info(format!("{}: skipped, {} can't receive mail", lead.label(), domain));
+                return Ok(None);
+            }
+            Err(e) => log::warn!("mx precheck failed domain={} error=\"{}\"", domain, e),
+        }
+    }
+    for canditate_address in candidate_addresses(lead) {
+        let is_valid = single_address_validity_check(canditate_address.as_str(), provider);
         match is_valid {
             Err(e) => return Err(e),
-            Ok(is_valid) if is_valid => {
-                return Ok(Some(canditate_address));
+            Ok(Some(status)) => {
+                let confidence = match status.as_str() {
+                    "accept_all" => catch_all_confidence(&domain),
+                    _ => EmailConfidence::Verified,
+                };
+                return Ok(Some(Found {
+                    email: canditate_address,
+                    status,
+                    confidence,
+                }));
             }
             _ => continue,
         }
     }
 
     //if we've reached here, no address was valid
-    return Ok(None);
+    Ok(None)
 }
 
-// keep rotating api in the loop until it works or ultimately fails
-// if api key is invalid, rotate it and call yourself, if its the last, return Err
-
-fn single_address_validity_check(
-    email: &str,
-    api_config: &mut ApiKeyRotation,
-    client: &Client,
-) -> anyhow::Result<bool> {
-    //make the api request
-    let base_url = "validect-email-verification-v1.p.rapidapi.com";
-    let request_url =
-        Url::parse(format!("https://{}/v1/verify?email={}", base_url, email).as_str()).unwrap();
-    println!("{}", request_url);
-    let resp = client
-        .get(request_url)
-        .headers(header_constructor(api_config, base_url))
-        .send();
-    match resp {
-        Err(e) => {
-            println!("{}: {}", "something went wrong".red(), e.to_string().red());
-            return Err(e.into());
-        }
-        Ok(ref r) if r.status() == 429 => {
-            println!("{}", "api key limit reached".red());
-            println!("{}", "rotating api key...".blue());
-            let rotation_result = api_config.rotate();
-            match rotation_result {
-                Err(e) => {
-                    println!("{}", e);
-                    return Err(e);
-                }
-                Ok(_) => {
-                    println!("{}", "successfuly rotated API key".blue());
-                    return single_address_validity_check(email, api_config, client);
-                }
-            }
+/// the first candidate of a catch-all domain follows the pattern verified there, when there is one
+fn catch_all_confidence(domain: &str) -> EmailConfidence {
+    match organizations::pattern_for(domain) {
+        Some(_) => EmailConfidence::Inferred,
+        None => EmailConfidence::Guessed,
+    }
+}
+
+/// the status of the address when it can receive email
+fn single_address_validity_check(email: &str, verifier: &dyn Verifier) -> AppResult<Option<String>> {
+    verifier.check(email)
+}
+
+impl Verifier for ProviderClient {
+    fn check(&self, email: &str) -> AppResult<Option<String>> {
+        //make the api request
+        let request_url = verify_url(email)?;
+        #[derive(Deserialize)]
+        struct EmailValidationResponse {
+            status: String,
         }
-        Ok(r) if !r.status().is_success() => {
-            println!(
-                "{}: {}",
-                "something went wrong".red(),
-                r.text().unwrap_or("".to_string())
-            );
-            return Err(anyhow!("something went wrong"));
+        let valid_response = self.get_json::<EmailValidationResponse>(&request_url)?;
+        self.progress().tried += 1;
+
+        match valid_response.status.as_str() {
+            "valid" | "accept_all" => Ok(Some(valid_response.status)),
+            _ => Ok(None),
         }
-        Ok(_) => {}
     }
-    #[derive(Deserialize)]
-    struct EmailValidationResponse {
-        status: String,
+
+    fn progress(&self) -> MutexGuard<'_, Progress> {
+        ProviderClient::progress(self)
+    }
+
+    fn reporter(&self) -> &Reporter {
+        ProviderClient::reporter(self)
     }
-    let valid_response = resp.unwrap().json::<EmailValidationResponse>()?;
+}
+
+/// migration recording catch-all companies and how sure each found email is
+/// nothing recorded how the emails found before were verified, so they stay unknown
+pub fn migrate(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(&format!(
+        "ALTER TABLE {} ADD COLUMN catchAll INTEGER;
+         ALTER TABLE {} ADD COLUMN emailConfidence TEXT;",
+        Organization::table_name(),
+        Lead::table_name()
+    ))
+}
 
-    return Ok(valid_response.status == "valid" || valid_response.status == "accept_all");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_parts_are_dot_atoms() {
+        assert!(valid_local_part("jane.doe"));
+        assert!(valid_local_part("j-doe_2"));
+        assert!(!valid_local_part(""));
+        assert!(!valid_local_part("jane doe"));
+        assert!(!valid_local_part("josé"));
+        assert!(!valid_local_part(".jane"));
+        assert!(!valid_local_part("jane..doe"));
+        assert!(!valid_local_part("jane>\r\nRCPT TO:<x"));
+        assert!(!valid_local_part("\"jane\""));
+    }
 }
@@ -0,0 +1,53 @@
+use inquire::InquireError;
+use thiserror::Error;
+
+/// everything that can go wrong while handling a screen
+#[derive(Debug, Error)]
+pub enum AppError {
+    /// the user pressed Esc or Ctrl+C in a prompt
+    #[error("cancelled")]
+    Cancelled,
+    #[error("network error: {0}")]
+    Network(String),
+    /// the provider answered, but not with what we asked for
+    #[error("provider error: {0}")]
+    Provider(String),
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("couldn't parse {0}")]
+    Parse(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl From<InquireError> for AppError {
+    fn from(e: InquireError) -> Self {
+        match e {
+            InquireError::OperationCanceled | InquireError::OperationInterrupted => Self::Cancelled,
+            e => Self::Other(format!("prompt failed: {}", e)),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            return Self::Parse(format!("the provider's response: {}", e));
+        }
+        Self::Network(e.to_string())
+    }
+}
+
+impl From<url::ParseError> for AppError {
+    fn from(e: url::ParseError) -> Self {
+        Self::Parse(format!("url: {}", e))
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Other(e.to_string())
+    }
+}
@@ -1,16 +1,15 @@
 use colorize::AnsiColor;
 use inquire::Select;
 
-use crate::{MenuState};
+use crate::{error::AppResult, MenuState};
 
-pub fn main_fetch() -> MenuState {
+pub fn main_fetch() -> AppResult<MenuState> {
     let options = vec!["Leads".blue(), "Emails".blue(), "Back".red()];
-    let selection = Select::new("Fetch".green().as_str(), options.clone()).prompt().unwrap();
+    let selection = Select::new("Fetch".green().as_str(), options.clone()).prompt()?;
         match options.iter().position( |x| *x == selection) {
-            Some(0) => MenuState::FetchLeads,
-            Some(1) => MenuState::FetchEmails,
-            Some(2) => MenuState::Main,
-            _ => panic!("something went wrong")
+            Some(0) => Ok(MenuState::FetchLeads),
+            Some(1) => Ok(MenuState::FetchEmails),
+            _ => Ok(MenuState::Main),
         }
     }
 
@@ -0,0 +1,293 @@
+use std::{
+    fs,
+    sync::{Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use reqwest::{
+    blocking::{Client, Response},
+    header::{HeaderMap, HeaderValue},
+    Certificate, NoProxy, Proxy,
+};
+use serde::de::DeserializeOwned;
+use url::Url;
+
+use crate::{
+    apikey::{ApiKey, ApiKeyRotation},
+    config,
+    error::{AppError, AppResult},
+    jobs::{lock, Reporter},
+    logging,
+    progress::Progress,
+    ratelimit::Throttle,
+    retry::{classify, RetryPolicy, Verdict},
+    traffic::{self, Traffic},
+};
+
+/// shown every time a client is built with certificate verification turned off
+pub const INSECURE_TLS_WARNING: &str = "WARNING: TLS certificate verification is turned off (tls.accept_invalid_certs), \
+anyone on the network can intercept your API keys";
+
+/// an API we send requests to, `settings` names its `http.<settings>.*` and `ratelimit.<settings>.*` settings
+pub struct Provider {
+    pub settings: &'static str,
+}
+
+pub const LEADS_PROVIDER: Provider = Provider { settings: "leads" };
+
+pub const EMAIL_PROVIDER: Provider = Provider { settings: "email" };
+
+impl Provider {
+    pub fn host(&self) -> String {
+        config::get(&format!("http.{}.host", self.settings)).trim().to_string()
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(config::get_u64(&format!("http.{}.timeout_secs", self.settings)).max(1))
+    }
+}
+
+/// sends the requests of one job to a provider, rotating through its API keys
+/// shared by every worker of the job
+pub struct ProviderClient {
+    host: String,
+    client: Client,
+    api_keys: Mutex<ApiKeyRotation>,
+    policy: RetryPolicy,
+    throttle: Throttle,
+    progress: Mutex<Progress>,
+    reporter: Reporter,
+}
+
+impl ProviderClient {
+    pub fn new(
+        provider: &Provider,
+        api_keys: Vec<ApiKey>,
+        progress: Progress,
+        reporter: &Reporter,
+    ) -> AppResult<Self> {
+        if api_keys.is_empty() {
+            return Err(AppError::Other(format!(
+                "no api keys for {}, add one in Settings > API Keys",
+                provider.settings
+            )));
+        }
+        for api_key in api_keys.iter() {
+            logging::add_secret(&api_key.key, api_key.masked());
+        }
+        let host = provider.host();
+        Ok(Self {
+            throttle: Throttle::for_provider(&host, provider.settings),
+            host,
+            client: build_client(provider, reporter)?,
+            api_keys: Mutex::new(ApiKeyRotation {
+                active_index: 0,
+                api_keys_avaialble: api_keys,
+            }),
+            policy: RetryPolicy::from_settings(),
+            progress: Mutex::new(progress),
+            reporter: reporter.clone(),
+        })
+    }
+
+    pub fn reporter(&self) -> &Reporter {
+        &self.reporter
+    }
+
+    /// progress of the job, counting the requests sent through this client
+    pub fn progress(&self) -> MutexGuard<'_, Progress> {
+        lock(&self.progress)
+    }
+
+    /// sends a GET request and parses the JSON response,
+    /// or serves the recorded one back when replaying
+    pub fn get_json<T: DeserializeOwned>(&self, url: &Url) -> AppResult<T> {
+        self.reporter.info(url.to_string());
+        let body = match traffic::mode() {
+            Traffic::Replay(dir) => {
+                let recording = traffic::load(dir, url)?;
+                self.progress().request("replay".to_string());
+                log::info!("replayed url={} status={}", url, recording.status);
+                if !(200..300).contains(&recording.status) {
+                    return Err(AppError::Provider(format!("{}: {}", recording.status, recording.body)));
+                }
+                recording.body
+            }
+            Traffic::Record(dir) => {
+                let response = self.send(url)?;
+                let status = response.status().as_u16();
+                let body = response.text()?;
+                match traffic::save(dir, url, status, &body) {
+                    Ok(path) => log::info!("recorded url={} file={}", url, path.display()),
+                    Err(e) => self.reporter.error(format!("couldn't record the response: {}", e)),
+                }
+                body
+            }
+            Traffic::Live => self.send(url)?.text()?,
+        };
+        serde_json::from_str(&body).map_err(|e| AppError::Parse(format!("the provider's response: {}", e)))
+    }
+
+    /// sends the request for the active API key once the rate limits allow it,
+    /// retrying transient failures and rotating the key once its quota is used up
+    fn send(&self, url: &Url) -> AppResult<Response> {
+        let mut attempt = 0;
+        loop {
+            let (key_index, api_key) = {
+                let keys = lock(&self.api_keys);
+                (keys.active_index, keys.get()?)
+            };
+            self.throttle.acquire(&api_key.key);
+            {
+                let mut progress = self.progress();
+                progress.request(api_key.masked());
+                self.reporter.progress(&progress);
+            }
+            let started = Instant::now();
+            let result = self
+                .client
+                .get(url.clone())
+                .headers(self.headers(&api_key)?)
+                .send();
+            let latency = started.elapsed().as_millis();
+            match &result {
+                Ok(response) => log::info!(
+                    "request url={} key={} status={} latency_ms={}",
+                    url,
+                    api_key.masked(),
+                    response.status().as_u16(),
+                    latency
+                ),
+                Err(e) => log::warn!(
+                    "request url={} key={} error=\"{}\" latency_ms={}",
+                    url,
+                    api_key.masked(),
+                    e,
+                    latency
+                ),
+            }
+            match classify(result, &self.policy, attempt) {
+                Verdict::Success(response) => return Ok(response),
+                Verdict::Retry { reason, response, .. } if attempt >= self.policy.max_retries => {
+                    log::error!("giving up url={} retries={} reason=\"{}\"", url, attempt, reason);
+                    if let Some(response) = response {
+                        self.failed(url, response);
+                    }
+                    return Err(AppError::Network(format!(
+                        "{}, giving up after {} retries",
+                        reason, self.policy.max_retries
+                    )));
+                }
+                Verdict::Retry { delay, reason, .. } => {
+                    attempt += 1;
+                    self.reporter.error(format!(
+                        "{}, retrying in {:.1}s ({}/{})",
+                        reason,
+                        delay.as_secs_f64(),
+                        attempt,
+                        self.policy.max_retries
+                    ));
+                    thread::sleep(delay);
+                }
+                Verdict::QuotaExhausted { reason, response } => {
+                    self.reporter.error(reason);
+                    self.reporter.info("rotating api key...");
+                    let next = lock(&self.api_keys).rotate_past(key_index);
+                    match &next {
+                        Ok(next) => log::info!("key rotated from={} to={}", api_key.masked(), next.masked()),
+                        Err(e) => {
+                            log::error!("key rotation failed from={} error=\"{}\"", api_key.masked(), e);
+                            self.failed(url, response);
+                        }
+                    }
+                    next?;
+                    self.reporter.info("successfuly rotated API key");
+                    attempt = 0;
+                }
+                Verdict::Refused(response) => {
+                    let e = AppError::Provider(self.failed(url, response));
+                    log::error!("request failed url={} error=\"{}\"", url, e);
+                    return Err(e);
+                }
+                Verdict::Fail(e) => {
+                    log::error!("request failed url={} error=\"{}\"", url, e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// reads the response ending a request unsuccessfully, recording it when recording so a replay fails the same way
+    /// returns its status and body
+    fn failed(&self, url: &Url, response: Response) -> String {
+        let status = response.status();
+        let body = response.text().unwrap_or_default();
+        if let Traffic::Record(dir) = traffic::mode() {
+            match traffic::save(dir, url, status.as_u16(), &body) {
+                Ok(path) => log::info!("recorded url={} status={} file={}", url, status.as_u16(), path.display()),
+                Err(e) => self.reporter.error(format!("couldn't record the response: {}", e)),
+            }
+        }
+        format!("{}: {}", status, body)
+    }
+
+    fn headers(&self, api_key: &ApiKey) -> AppResult<HeaderMap> {
+        let key = HeaderValue::from_str(api_key.key.as_str()).map_err(|_| {
+            AppError::Parse(format!("the API key {}, it contains characters not allowed in a header", api_key.masked()))
+        })?;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-rapidapi-key", key);
+        let host = HeaderValue::from_str(&self.host)
+            .map_err(|_| AppError::Parse(format!("the host {}, it contains characters not allowed in a header", self.host)))?;
+        headers.insert("x-rapidapi-host", host);
+        Ok(headers)
+    }
+}
+
+/// certificates are verified against the system roots and the `tls.ca_bundle` if one is set,
+/// requests go through `proxy.url` if one is set
+fn build_client(provider: &Provider, reporter: &Reporter) -> AppResult<Client> {
+    let mut builder = Client::builder().timeout(provider.timeout());
+
+    let ca_bundle = config::get("tls.ca_bundle");
+    let ca_bundle = ca_bundle.trim();
+    if !ca_bundle.is_empty() {
+        let pem = fs::read(ca_bundle)
+            .map_err(|e| AppError::Other(format!("couldn't read the CA bundle {}: {}", ca_bundle, e)))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| AppError::Parse(format!("the CA bundle {}: {}", ca_bundle, e)))?;
+        if certificates.is_empty() {
+            return Err(AppError::Parse(format!("the CA bundle {}, it doesn't contain any certificate", ca_bundle)));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    let proxy_url = config::get("proxy.url");
+    let proxy_url = proxy_url.trim();
+    if !proxy_url.is_empty() {
+        if let Ok(url) = Url::parse(proxy_url)
+            && let Some(password) = url.password()
+        {
+            logging::add_secret(password, "****".to_string());
+        }
+        //credentials in the url are sent to the proxy as basic auth
+        let proxy = Proxy::all(proxy_url)
+            .map_err(|e| AppError::Parse(format!("proxy.url: {}", e)))?
+            .no_proxy(NoProxy::from_string(&config::get("proxy.no_proxy")));
+        builder = builder.proxy(proxy);
+    }
+
+    let user_agent = config::get("http.user_agent");
+    if !user_agent.trim().is_empty() {
+        builder = builder.user_agent(user_agent.trim());
+    }
+
+    if config::get_bool("tls.accept_invalid_certs") {
+        reporter.error(INSECURE_TLS_WARNING);
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,355 @@
+use std::{collections::HashSet, path::Path, sync::LazyLock};
+
+use chrono::Utc;
+use colorize::AnsiColor;
+use csv::{ReaderBuilder, StringRecord, Trim, WriterBuilder};
+use inquire::{validator::Validation, Confirm, MultiSelect, Select, Text};
+use prettytable::{row, Table};
+use regex::Regex;
+
+use crate::{
+    clear_and_logo,
+    db::{list_all, list_selection, open_connection, DBentity, ListSelectedResult},
+    empty_validator,
+    error::{AppError, AppResult},
+    classify::EmailKind,
+    jobs::ListLock,
+    leads::{confidence_label, kind_label, EmailConfidence, Lead, LeadField},
+    MenuState,
+};
+
+static EMAIL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap());
+
+/// header names, lowercased and without separators, recognised for each field
+fn aliases(field: LeadField) -> &'static [&'static str] {
+    match field {
+        LeadField::FirstName => &["firstname", "first", "givenname", "forename"],
+        LeadField::LastName => &["lastname", "last", "surname", "familyname"],
+        LeadField::Name => &["name", "fullname", "contact", "contactname"],
+        LeadField::Title => &["title", "jobtitle", "position", "role"],
+        LeadField::LinkedinUrl => &["linkedin", "linkedinurl", "linkedinprofile"],
+        LeadField::City => &["city", "town"],
+        LeadField::State => &["state", "region", "province"],
+        LeadField::Country => &["country"],
+        LeadField::OrgName => &["organization", "organisation", "company", "companyname", "org", "employer"],
+        LeadField::OrgWebsite => &["organizationwebsite", "website", "companywebsite", "domain", "url", "web"],
+        LeadField::OrgFacebookUrl => &["organizationfacebookurl", "facebook", "companyfacebook"],
+        LeadField::OrgLinkedinUrl => &["organizationlinkedinurl", "companylinkedin", "companylinkedinurl"],
+        LeadField::Email => &["email", "emailaddress", "mail"],
+    }
+}
+
+fn normalize(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn csv_error(e: csv::Error) -> AppError {
+    AppError::Parse(format!("the CSV file: {}", e))
+}
+
+/// imports the rows of a CSV file as leads of a list, so they go through the
+/// email fetching like fetched leads
+pub fn import_csv() -> AppResult<MenuState> {
+    let mut list = match list_selection()? {
+        ListSelectedResult::Back => return Ok(MenuState::Lists),
+        ListSelectedResult::NoLists => {
+            println!("{}", "create a list to import the leads into first".red());
+            return Ok(MenuState::Lists);
+        }
+        ListSelectedResult::ListSelected(list) => *list,
+    };
+    //the leads are appended and counted, a job on the list would lose count
+    let _list_lock = match ListLock::acquire(&list) {
+        Ok(list_lock) => list_lock,
+        Err(e) => {
+            println!("{}", e.to_string().red());
+            return Ok(MenuState::Lists);
+        }
+    };
+    let path = Text::new("path of the CSV file:".blue().as_str())
+        .with_validator(|v: &str| {
+            if Path::new(v.trim()).is_file() {
+                Ok(Validation::Valid)
+            } else {
+                Ok(Validation::Invalid("no file at this path".into()))
+            }
+        })
+        .prompt()?;
+
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .trim(Trim::All)
+        .from_path(path.trim())
+        .map_err(csv_error)?;
+    let headers = reader
+        .headers()
+        .map_err(csv_error)?
+        .iter()
+        .map(String::from)
+        .collect::<Vec<String>>();
+    let mapping = map_columns(&headers)?;
+    let mapped = |field: LeadField| mapping.iter().any(|(f, _)| *f == field);
+    if !mapped(LeadField::Name) && !mapped(LeadField::FirstName) {
+        println!("{}", "a name or first name column is needed to import leads".red());
+        return Ok(MenuState::Lists);
+    }
+
+    let existing = list_all::<Lead>(
+        Some(format!("SELECT * FROM {} WHERE listId = {}", Lead::table_name(), list.id)),
+        None,
+    )?
+    .items;
+    let mut seen = existing.iter().flat_map(dedup_keys).collect::<HashSet<String>>();
+
+    //a failing row leaves the list as it was, not half imported
+    let mut connection = open_connection()?;
+    let transaction = connection.transaction()?;
+    let batch = Utc::now().timestamp_millis();
+    let mut imported = 0;
+    let mut duplicates = 0;
+    let mut invalid = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        //the header is the first row
+        let row = index + 2;
+        let lead = record
+            .map_err(|e| e.to_string())
+            .and_then(|record| lead_from_record(&record, &mapping, format!("csv-{}-{}", batch, row), list.id));
+        let lead = match lead {
+            Ok(lead) => lead,
+            Err(reason) => {
+                invalid.push(format!("row {}: {}", row, reason));
+                continue;
+            }
+        };
+        let keys = dedup_keys(&lead);
+        if keys.iter().any(|key| seen.contains(key)) {
+            duplicates += 1;
+            continue;
+        }
+        lead.insert_with(&transaction)?;
+        seen.extend(keys);
+        imported += 1;
+    }
+    if imported > 0 {
+        list.update_meta_with(&transaction, None, Some(list.leads_fetched + imported), None)?;
+    }
+    transaction.commit()?;
+    log::info!(
+        "csv import file={} list={} imported={} duplicates={} invalid={}",
+        path.trim(),
+        list.name,
+        imported,
+        duplicates,
+        invalid.len()
+    );
+
+    println!("{} {} {}", "imported".blue(), imported, format!("leads into {}", list.name).blue());
+    if duplicates > 0 {
+        println!("{} {}", "skipped duplicates:".blue(), duplicates);
+    }
+    if !invalid.is_empty() {
+        println!("{} {}", "skipped invalid rows:".red(), invalid.len());
+        for reason in invalid.iter().take(10) {
+            println!("  {}", reason);
+        }
+        if invalid.len() > 10 {
+            println!("  ... and {} more", invalid.len() - 10);
+        }
+    }
+    println!();
+    Ok(MenuState::Lists)
+}
+
+/// which column, if any, fills each field, guessed from the headers and confirmed by the user
+fn map_columns(headers: &[String]) -> AppResult<Vec<(LeadField, usize)>> {
+    let normalized = headers.iter().map(|h| normalize(h)).collect::<Vec<String>>();
+    let mut mapping = LeadField::ALL
+        .into_iter()
+        .filter_map(|field| {
+            let column = normalized.iter().position(|header| aliases(field).contains(&header.as_str()))?;
+            Some((field, column))
+        })
+        .collect::<Vec<(LeadField, usize)>>();
+
+    let mut table = Table::new();
+    table.add_row(row!["field", "column"]);
+    for field in LeadField::ALL {
+        let column = mapping
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, column)| headers[*column].clone())
+            .unwrap_or("-".to_string());
+        table.add_row(row![field.to_str(), column]);
+    }
+    clear_and_logo("Import leads from CSV".to_string());
+    table.printstd();
+    if Confirm::new("use these columns ?").with_default(true).prompt()? {
+        return Ok(mapping);
+    }
+
+    mapping.clear();
+    let mut options = vec!["(skip)".to_string()];
+    options.extend(headers.iter().cloned());
+    for field in LeadField::ALL {
+        let guess = normalized
+            .iter()
+            .position(|header| aliases(field).contains(&header.as_str()))
+            .map_or(0, |column| column + 1);
+        let picked = Select::new(format!("column for {}:", field.to_str()).as_str(), options.clone())
+            .with_starting_cursor(guess)
+            .raw_prompt()?;
+        if picked.index > 0 {
+            mapping.push((field, picked.index - 1));
+        }
+    }
+    Ok(mapping)
+}
+
+/// builds a lead from a row, or says why the row can't be imported
+fn lead_from_record(
+    record: &StringRecord,
+    mapping: &[(LeadField, usize)],
+    id: String,
+    list_id: u32,
+) -> Result<Lead, String> {
+    let mut lead = Lead::blank(id, list_id);
+    for (field, column) in mapping {
+        let value = record.get(*column).map(str::trim).filter(|v| !v.is_empty());
+        lead.set(*field, value.map(String::from));
+    }
+
+    //fill in whichever of the full name and its parts is missing
+    let first = lead.get(LeadField::FirstName).unwrap_or_default();
+    let last = lead.get(LeadField::LastName).unwrap_or_default();
+    let name = lead.get(LeadField::Name).unwrap_or_default();
+    if name.is_empty() {
+        lead.set(LeadField::Name, Some(format!("{} {}", first, last).trim().to_string()));
+    } else if first.is_empty() {
+        let (first, last) = name.split_once(' ').unwrap_or((name.as_str(), ""));
+        lead.set(LeadField::FirstName, Some(first.to_string()));
+        lead.set(LeadField::LastName, Some(last.trim().to_string()));
+    }
+    if lead.get(LeadField::Name).unwrap_or_default().is_empty() {
+        return Err("no name".to_string());
+    }
+
+    if let Some(email) = lead.get(LeadField::Email) {
+        if !EMAIL.is_match(&email) {
+            return Err(format!("invalid email {}", email));
+        }
+        lead.set(LeadField::Email, Some(email.to_lowercase()));
+    }
+    Ok(lead)
+}
+
+/// a lead is a duplicate when its email, or its name at the same organization, is already in the list
+fn dedup_keys(lead: &Lead) -> Vec<String> {
+    let mut keys = vec![format!(
+        "name:{}|{}",
+        lead.get(LeadField::Name).unwrap_or_default().to_lowercase(),
+        lead.get(LeadField::OrgName).unwrap_or_default().to_lowercase()
+    )];
+    if let Some(email) = lead.get(LeadField::Email) {
+        keys.push(format!("email:{}", email.to_lowercase()));
+    }
+    keys
+}
+
+/// which leads of a list are written to the CSV file
+const EXPORT_CHOICES: [&str; 4] = [
+    "every lead",
+    "leads with an email",
+    "verified and inferred emails",
+    "verified emails only",
+];
+
+/// writes the leads of a list to a CSV file, with the confidence and kind of their emails
+/// the headers are the field names, so the file can be imported back
+pub fn export_csv() -> AppResult<MenuState> {
+    let list = match list_selection()? {
+        ListSelectedResult::Back | ListSelectedResult::NoLists => return Ok(MenuState::Export),
+        ListSelectedResult::ListSelected(list) => *list,
+    };
+    let choice = Select::new("which leads ?", EXPORT_CHOICES.to_vec()).raw_prompt()?;
+    let confidences = match choice.index {
+        0 => None,
+        1 => Some(vec![None, Some(EmailConfidence::Verified), Some(EmailConfidence::Inferred), Some(EmailConfidence::Guessed)]),
+        2 => Some(vec![Some(EmailConfidence::Verified), Some(EmailConfidence::Inferred)]),
+        _ => Some(vec![Some(EmailConfidence::Verified)]),
+    };
+    let kinds = match confidences {
+        None => EmailKind::ALL.to_vec(),
+        Some(_) => {
+            let labels = EmailKind::ALL.iter().map(|kind| kind.to_str()).collect::<Vec<&str>>();
+            MultiSelect::new("which kinds of email ? (space to toggle, enter to confirm)", labels)
+                .with_all_selected_by_default()
+                .with_validator(|v: &[inquire::list_option::ListOption<&&str>]| {
+                    if v.is_empty() {
+                        Ok(Validation::Invalid("please select a kind".into()))
+                    } else {
+                        Ok(Validation::Valid)
+                    }
+                })
+                .raw_prompt()?
+                .into_iter()
+                .map(|picked| EmailKind::ALL[picked.index])
+                .collect()
+        }
+    };
+    let default_path = format!("leads-{}.csv", Utc::now().format("%Y%m%d-%H%M%S"));
+    let path = Text::new("save the CSV file to:".blue().as_str())
+        .with_default(&default_path)
+        .with_validator(empty_validator)
+        .prompt()?;
+    let path = path.trim();
+    if Path::new(path).exists()
+        && !Confirm::new(format!("{} already exists, overwrite it ?", path).as_str())
+            .with_default(false)
+            .prompt()?
+    {
+        return Ok(MenuState::Export);
+    }
+
+    let leads = list_all::<Lead>(
+        Some(format!("SELECT * FROM {} WHERE listId = {} ORDER BY rowid", Lead::table_name(), list.id)),
+        None,
+    )?
+    .items
+    .into_iter()
+    .filter(|lead| match &confidences {
+        None => true,
+        Some(confidences) => {
+            lead.email.is_some()
+                && confidences.contains(&lead.email_confidence)
+                && lead.email_kind.is_none_or(|kind| kinds.contains(&kind))
+        }
+    })
+    .collect::<Vec<Lead>>();
+
+    let mut writer = WriterBuilder::new().from_path(path).map_err(csv_error)?;
+    let mut headers = LeadField::ALL.iter().map(|field| field.to_str()).collect::<Vec<&str>>();
+    headers.push("email confidence");
+    headers.push("email kind");
+    writer.write_record(&headers).map_err(csv_error)?;
+    for lead in leads.iter() {
+        let mut record = LeadField::ALL
+            .iter()
+            .map(|field| lead.get(*field).unwrap_or_default())
+            .collect::<Vec<String>>();
+        record.push(match lead.email {
+            Some(_) => confidence_label(lead.email_confidence),
+            None => String::new(),
+        });
+        record.push(kind_label(lead.email_kind));
+        writer.write_record(&record).map_err(csv_error)?;
+    }
+    writer.flush()?;
+
+    log::info!("csv exported file={} list={} leads={} which=\"{}\"", path, list.name, leads.len(), choice);
+    println!("{} {} {} {}\n", "exported".blue(), leads.len(), "leads to".blue(), path);
+    Ok(MenuState::Export)
+}
@@ -0,0 +1,182 @@
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::Sender,
+        Mutex, MutexGuard,
+    },
+};
+
+use colorize::AnsiColor;
+
+use crate::{
+    error::{AppError, AppResult},
+    list::List,
+    progress::Progress,
+};
+
+/// set by `--dry-run`, fetches then only describe what they would do
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// fetches running in the background, the workspace can't be switched under them
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// counts a fetch as running until it is dropped
+pub struct Running;
+
+impl Running {
+    pub fn start() -> Self {
+        RUNNING.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn running() -> usize {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// ids of the lists something is writing to, two jobs on one list would both start from the same next pointer and lead
+static LOCKED_LISTS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// holds a list for one job or edit until it is dropped
+pub struct ListLock(u32);
+
+impl ListLock {
+    pub fn acquire(list: &List) -> AppResult<Self> {
+        let mut locked = lock(&LOCKED_LISTS);
+        if locked.contains(&list.id) {
+            return Err(AppError::Other(format!(
+                "a job is already running for {}, wait for it to finish",
+                list.name
+            )));
+        }
+        locked.push(list.id);
+        Ok(Self(list.id))
+    }
+}
+
+impl Drop for ListLock {
+    fn drop(&mut self) {
+        lock(&LOCKED_LISTS).retain(|id| *id != self.0);
+    }
+}
+
+//moves to the start of the line and erases it, so messages replace the progress bar
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// a message emitted by a running fetch
+pub enum JobEvent {
+    Info(String),
+    Error(String),
+    Progress(Progress),
+    Finished,
+}
+
+/// where a fetch reports its progress to
+/// the prompt based menus print to the console, the TUI listens on a channel
+#[derive(Clone)]
+pub enum Reporter {
+    Console,
+    Channel {
+        job: usize,
+        sender: Sender<(usize, JobEvent)>,
+    },
+}
+
+impl Reporter {
+    pub fn info(&self, message: impl Into<String>) {
+        let message = message.into();
+        log::debug!("{}", message);
+        match self {
+            Self::Console => println!("{}{}", CLEAR_LINE, message.blue()),
+            Self::Channel { job, sender } => {
+                _ = sender.send((*job, JobEvent::Info(message)));
+            }
+        }
+    }
+
+    pub fn error(&self, message: impl Into<String>) {
+        let message = message.into();
+        log::warn!("{}", message);
+        match self {
+            Self::Console => println!("{}{}", CLEAR_LINE, message.red()),
+            Self::Channel { job, sender } => {
+                _ = sender.send((*job, JobEvent::Error(message)));
+            }
+        }
+    }
+
+    pub fn progress(&self, progress: &Progress) {
+        match self {
+            Self::Console => {
+                print!("{}{}", CLEAR_LINE, progress.bar().green());
+                _ = io::stdout().flush();
+            }
+            Self::Channel { job, sender } => {
+                _ = sender.send((*job, JobEvent::Progress(progress.clone())));
+            }
+        }
+    }
+
+    pub fn finished(&self) {
+        match self {
+            Self::Console => println!(),
+            Self::Channel { job, sender } => {
+                _ = sender.send((*job, JobEvent::Finished));
+            }
+        }
+    }
+}
+
+/// locks state shared between the workers of a job, a panicked worker doesn't make it unusable
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::list::{EmployeeSize, ListFilter};
+
+    fn list(id: u32) -> List {
+        List {
+            id,
+            name: format!("list {}", id),
+            leads_fetched: 0,
+            emails_fetched: 0,
+            filter: ListFilter {
+                person_title: String::new(),
+                location: String::new(),
+                industry: String::new(),
+                keywords: None,
+                employee_size: EmployeeSize::Unspecified,
+            },
+            next_pointer: None,
+        }
+    }
+
+    #[test]
+    fn a_list_is_locked_once_until_dropped() {
+        //ids no other test locks
+        let first = ListLock::acquire(&list(9001)).unwrap();
+        assert!(ListLock::acquire(&list(9001)).is_err());
+        let other = ListLock::acquire(&list(9002));
+        assert!(other.is_ok());
+        drop(first);
+        assert!(ListLock::acquire(&list(9001)).is_ok());
+    }
+}
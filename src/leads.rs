@@ -1,18 +1,14 @@
-use std::{error::Error, time::Duration};
+use std::error::Error;
 
-use anyhow::{ Result};
 use colorize::AnsiColor;
 use inquire::{Text, validator::Validation};
-use reqwest::{
-    blocking::Client,
-    header::{HeaderMap, HeaderValue},
-};
-use rusqlite::Connection;
 use serde::{Deserialize, Deserializer};
+use rusqlite::Connection;
 use url::Url;
-use crate::{apikey::ApiKeyFor, db::{list_selection, ListSelectedResult}};
+use crate::{apikey::{ApiKey, ApiKeyFor}, backup, classify::{self, EmailKind}, config, db::{list_selection, ListSelectedResult}, domain, error::{AppError, AppResult}, http::{ProviderClient, LEADS_PROVIDER}, jobs::{self, ListLock, Reporter}, organizations, progress::{Progress, ProgressKind}};
+
 use crate::{
-    apikey::{ApiKeyRotation}, db::{api_keys_available, open_connection, DBentity, Tablular}, empty_validator, list::{EmployeeSize, List, ListFilter}, MenuState
+    db::{api_keys_available, open_connection, DBentity, Tablular}, empty_validator, list::{EmployeeSize, List, ListFilter}, MenuState
 };
 
 pub fn num_validator(v: &str) -> Result<Validation, Box<dyn Error + Send + Sync>> {
@@ -27,68 +23,107 @@ pub fn num_validator(v: &str) -> Result<Validation, Box<dyn Error + Send + Sync>
 
 //input
 
-pub fn fetch_leads() -> MenuState {
-    let selection_maybe = list_selection();
-    let selected_list_maybe: Option<List>;
-    if let Err(e) = selection_maybe {
-        println!("something went wrong {}", e);
-        return MenuState::GoodBye;
-    }
-    let selection = selection_maybe.unwrap();
-    match selection {
-        ListSelectedResult::Back => {return MenuState::Fetch},
-        ListSelectedResult::NoLists => {return MenuState::Lists},
-        ListSelectedResult::ListSelected(list) => {selected_list_maybe = Some(list)}
+pub fn fetch_leads() -> AppResult<MenuState> {
+    let mut selected_list = match list_selection()? {
+        ListSelectedResult::Back => return Ok(MenuState::Fetch),
+        ListSelectedResult::NoLists => return Ok(MenuState::Lists),
+        ListSelectedResult::ListSelected(list) => *list,
+    };
+    let list_lock = match ListLock::acquire(&selected_list) {
+        Ok(list_lock) => list_lock,
+        Err(e) => {
+            println!("{}", e.to_string().red());
+            return Ok(MenuState::Fetch);
+        }
     };
-
-    let mut selected_list = selected_list_maybe.unwrap();
 
     //now ask how many leads to fetch
     let fetch_count = Text::new("how many leads do you want to fetch ?")
         .with_validators(&[Box::new(empty_validator), Box::new(num_validator)])
-        .prompt()
-        .unwrap()
+        .prompt()?
+        .trim()
         .parse::<u32>()
-        .unwrap();
+        .map_err(|e| AppError::Parse(format!("the number of leads: {}", e)))?;
     println!(
         "fetching {} leads for list {}",
         fetch_count, selected_list.name
     );
 
-    let maybe_api_keys = api_keys_available(ApiKeyFor::Leads);
-    if matches!(maybe_api_keys, None){
-        return MenuState::APIkeys;
+    let Some(api_keys) = api_keys_available(ApiKeyFor::Leads)? else {
+        return Ok(MenuState::APIkeys);
+    };
+    run_leads_job(&mut selected_list, list_lock, fetch_count, api_keys, &Reporter::Console);
+    Ok(MenuState::Fetch)
+}
+
+/// fetches `count` leads into the list, shared by the prompt based menus and the TUI
+/// the list stays locked until the job is done
+pub fn run_leads_job(list: &mut List, _list_lock: ListLock, count: u32, apikeys: Vec<ApiKey>, reporter: &Reporter) -> MenuState {
+    let _running = jobs::Running::start();
+    if jobs::dry_run() {
+        plan_leads_job(list, count, &apikeys, reporter);
+        return MenuState::Fetch;
     }
-    let apikeys = maybe_api_keys.unwrap();
-    let mut api_key_config = ApiKeyRotation {
-        active_index: 0,
-        api_keys_avaialble: apikeys,
+    backup::before_job(reporter);
+    let progress = Progress::new(ProgressKind::Leads, count);
+    let provider = match ProviderClient::new(&LEADS_PROVIDER, apikeys, progress, reporter) {
+        Ok(provider) => provider,
+        Err(e) => {
+            reporter.error(format!("couldn't set up the http client: {}", e));
+            reporter.finished();
+            return MenuState::Main;
+        }
     };
+    let state = leads_fetcher_from_api(list, &provider, reporter);
+    reporter.finished();
+    state
+}
 
-    //url
-    let base_url = url_parser(&selected_list);
-    let client = Client::builder()
-        .danger_accept_invalid_certs(true)
-        .timeout(Duration::from_secs(60))
-        .build()
-        .unwrap();
-    leads_fetcher_from_api(
-        &mut selected_list,
-        fetch_count,
-        client,
-        0,
-        open_connection(),
-        &mut api_key_config,
-        base_url,
-    );
-    MenuState::Fetch
+/// how many leads the provider usually returns per request, it picks the page size itself
+const USUAL_PAGE_SIZE: u32 = 25;
+
+/// describes what `run_leads_job` would send, without sending anything or touching the database
+fn plan_leads_job(list: &List, count: u32, apikeys: &[ApiKey], reporter: &Reporter) {
+    reporter.info("dry run, no request is sent and nothing is saved");
+    if list.leads_fetched > 0 && list.next_pointer.is_none() {
+        reporter.error("cannot fetch leads, no more data available to fetch");
+    }
+    match url_parser(list) {
+        Ok(mut url) => {
+            make_url_with_next(&mut url, list);
+            reporter.info(format!("first request: GET {}", url));
+        }
+        Err(e) => reporter.error(format!("couldn't build the request url: {}", e)),
+    }
+    let keys = apikeys.iter().map(|key| key.masked()).collect::<Vec<String>>();
+    reporter.info(format!(
+        "{} api key(s): {}, starting with {} and rotating to the next once its quota is used up",
+        keys.len(),
+        keys.join(", "),
+        keys[0]
+    ));
+    reporter.info(format!(
+        "quota: {}/s and {}/min for the provider, {}/s and {}/min per key (0 is no limit)",
+        config::get_u64("ratelimit.leads.per_second"),
+        config::get_u64("ratelimit.leads.per_minute"),
+        config::get_u64("ratelimit.key.per_second"),
+        config::get_u64("ratelimit.key.per_minute")
+    ));
+    //the job stops once it stored `count` new leads, the ones the list already has don't count
+    //the request has no page size, so the number of pages is only an estimate
+    let pages = count.div_ceil(USUAL_PAGE_SIZE);
+    reporter.info(format!(
+        "{} leads at about {} per page: about {} request(s), following the provider's next pointer after the {} leads the list has",
+        count, USUAL_PAGE_SIZE, pages, list.leads_fetched
+    ));
+    reporter.finished();
 }
 
 //structures
 
 fn truncate(s: &str, max: usize) -> String {
-    if s.len() > max {
-        return format!("{}...", &s[..max]);
+    if s.chars().count() > max {
+        format!("{}...", s.chars().take(max).collect::<String>())
     } else {
         s.to_string()
     }
@@ -102,7 +137,7 @@ where
     if s.is_empty() { Ok(None) } else { Ok(Some(s)) }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct Lead {
     pub id: String,
     #[serde(rename(deserialize = "firstName"))]
@@ -136,15 +171,274 @@ pub struct Lead {
     pub email: Option<String>,
     #[serde(skip)]
     list_id: u32,
+    /// set when the email was found by a job, none for emails typed in or imported
+    #[serde(skip)]
+    pub email_confidence: Option<EmailConfidence>,
+    /// classified from the email whenever it is stored
+    #[serde(skip)]
+    pub email_kind: Option<EmailKind>,
+}
+
+/// how sure we are that a found email reaches the lead
+#[derive(Clone, Copy, PartialEq)]
+pub enum EmailConfidence {
+    /// the mail server confirmed this exact address
+    Verified,
+    /// the domain accepts any address, this one follows the pattern verified at the company
+    Inferred,
+    /// the domain accepts any address and no pattern was verified there yet
+    Guessed,
+}
+
+impl EmailConfidence {
+    pub const ALL: [EmailConfidence; 3] = [Self::Verified, Self::Inferred, Self::Guessed];
+
+    /// also how it is stored in the emailConfidence column
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Verified => "verified",
+            Self::Inferred => "inferred",
+            Self::Guessed => "guessed",
+        }
+    }
+
+    pub fn from_str(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|confidence| confidence.to_str() == value)
+    }
+}
+
+/// how the kind of an email is shown, empty without an email
+pub fn kind_label(kind: Option<EmailKind>) -> String {
+    kind.map(|kind| kind.to_str()).unwrap_or_default().to_string()
+}
+
+/// how the confidence of an email is shown, emails not found by a job have none
+pub fn confidence_label(confidence: Option<EmailConfidence>) -> String {
+    confidence.map(|c| c.to_str()).unwrap_or("unknown").to_string()
+}
+
+/// every stored field of a lead that can be corrected by hand
+#[derive(Clone, Copy, PartialEq)]
+pub enum LeadField {
+    FirstName,
+    LastName,
+    Name,
+    Title,
+    LinkedinUrl,
+    City,
+    State,
+    Country,
+    OrgName,
+    OrgWebsite,
+    OrgFacebookUrl,
+    OrgLinkedinUrl,
+    Email,
+}
+
+impl LeadField {
+    pub const ALL: [LeadField; 13] = [
+        Self::FirstName,
+        Self::LastName,
+        Self::Name,
+        Self::Title,
+        Self::LinkedinUrl,
+        Self::City,
+        Self::State,
+        Self::Country,
+        Self::OrgName,
+        Self::OrgWebsite,
+        Self::OrgFacebookUrl,
+        Self::OrgLinkedinUrl,
+        Self::Email,
+    ];
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::FirstName => "first name",
+            Self::LastName => "last name",
+            Self::Name => "name",
+            Self::Title => "title",
+            Self::LinkedinUrl => "LinkedIn URL",
+            Self::City => "city",
+            Self::State => "state",
+            Self::Country => "country",
+            Self::OrgName => "organization",
+            Self::OrgWebsite => "organization website",
+            Self::OrgFacebookUrl => "organization Facebook URL",
+            Self::OrgLinkedinUrl => "organization LinkedIn URL",
+            Self::Email => "email",
+        }
+    }
+
+    /// name of the column the field is stored in, also its key in exported bundles
+    pub fn column(self) -> &'static str {
+        match self {
+            Self::FirstName => "first_name",
+            Self::LastName => "last_name",
+            Self::Name => "name",
+            Self::Title => "title",
+            Self::LinkedinUrl => "linkedin_url",
+            Self::City => "city",
+            Self::State => "state",
+            Self::Country => "country",
+            Self::OrgName => "org_name",
+            Self::OrgWebsite => "org_website",
+            Self::OrgFacebookUrl => "org_fb_url",
+            Self::OrgLinkedinUrl => "org_linkedin_url",
+            Self::Email => "email",
+        }
+    }
+
+    /// optional fields are stored as NULL when cleared
+    pub fn is_optional(self) -> bool {
+        matches!(
+            self,
+            Self::City
+                | Self::State
+                | Self::OrgWebsite
+                | Self::OrgFacebookUrl
+                | Self::OrgLinkedinUrl
+                | Self::Email
+        )
+    }
 }
 
 impl Lead {
-   pub fn update_email(lead_id: String, email: String) -> Result<()>{
-        let query = format!("UPDATE {} SET email = ?1 WHERE id='{}';", Self::table_name(), lead_id);
-       open_connection().execute(&query, [email])?;
-       anyhow::Ok(())
+   pub fn update_email(lead_id: String, list_id: u32, email: String, confidence: EmailConfidence) -> AppResult<()>{
+        //the same person can be a lead of several lists
+        let query = format!("UPDATE {} SET email = ?1, emailConfidence = ?2, emailKind = ?3 WHERE id = ?4 AND listId = ?5;", Self::table_name());
+        let kind = classify::kind(&email).map(|kind| kind.to_str());
+       open_connection()?.execute(&query, (&email, confidence.to_str(), kind, &lead_id, list_id))?;
+       Ok(())
        
     }
+
+    pub fn get(&self, field: LeadField) -> Option<String> {
+        match field {
+            LeadField::FirstName => Some(self.first_name.clone()),
+            LeadField::LastName => Some(self.last_name.clone()),
+            LeadField::Name => Some(self.name.clone()),
+            LeadField::Title => Some(self.title.clone()),
+            LeadField::LinkedinUrl => Some(self.linkedin_url.clone()),
+            LeadField::City => self.city.clone(),
+            LeadField::State => self.state.clone(),
+            LeadField::Country => Some(self.country.clone()),
+            LeadField::OrgName => Some(self.org_name.clone()),
+            LeadField::OrgWebsite => self.org_website.clone(),
+            LeadField::OrgFacebookUrl => self.org_fb_url.clone(),
+            LeadField::OrgLinkedinUrl => self.org_linkedin_url.clone(),
+            LeadField::Email => self.email.clone(),
+        }
+    }
+
+    /// one line summary used when picking a lead from a page
+    pub fn label(&self) -> String {
+        format!("{} - {} ({})", self.name, self.title, self.org_name)
+    }
+
+    pub fn update_field(&mut self, field: LeadField, value: Option<String>) -> AppResult<()> {
+        let query = format!(
+            "UPDATE {} SET {} = ?1 WHERE id = ?2 AND listId = ?3;",
+            Self::table_name(),
+            field.column()
+        );
+        let connection = open_connection()?;
+        connection.execute(&query, (&value, &self.id, &self.list_id))?;
+        self.set(field, value);
+        //an email typed in wasn't checked by anyone
+        if field == LeadField::Email {
+            self.email_kind = self.email.as_deref().and_then(classify::kind);
+            connection.execute(
+                format!("UPDATE {} SET emailConfidence = NULL, emailKind = ?1 WHERE id = ?2 AND listId = ?3;", Self::table_name()).as_str(),
+                (self.email_kind.map(|kind| kind.to_str()), &self.id, &self.list_id),
+            )?;
+            self.email_confidence = None;
+        }
+        //a new website can mean another company
+        if matches!(
+            field,
+            LeadField::OrgName | LeadField::OrgWebsite | LeadField::OrgFacebookUrl | LeadField::OrgLinkedinUrl
+        ) {
+            let org_id = organizations::link(&connection, self)?;
+            let domain = self.org_website.as_deref().and_then(domain::normalize);
+            connection.execute(
+                format!("UPDATE {} SET orgId = ?1, domain = ?2 WHERE id = ?3 AND listId = ?4;", Self::table_name()).as_str(),
+                (org_id, domain, &self.id, &self.list_id),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// a lead of the list that isn't stored yet, its fields are filled with `set`
+    pub fn blank(id: String, list_id: u32) -> Self {
+        Self {
+            id,
+            list_id,
+            ..Default::default()
+        }
+    }
+
+    /// changes a field without storing it, cleared fields that can't be NULL become empty
+    pub fn set(&mut self, field: LeadField, value: Option<String>) {
+        let text = value.clone().unwrap_or_default();
+        match field {
+            LeadField::FirstName => self.first_name = text,
+            LeadField::LastName => self.last_name = text,
+            LeadField::Name => self.name = text,
+            LeadField::Title => self.title = text,
+            LeadField::LinkedinUrl => self.linkedin_url = text,
+            LeadField::City => self.city = value,
+            LeadField::State => self.state = value,
+            LeadField::Country => self.country = text,
+            LeadField::OrgName => self.org_name = text,
+            LeadField::OrgWebsite => self.org_website = value,
+            LeadField::OrgFacebookUrl => self.org_fb_url = value,
+            LeadField::OrgLinkedinUrl => self.org_linkedin_url = value,
+            LeadField::Email => self.email = value,
+        }
+    }
+
+    /// position of the lead inside its list, in the order leads were added
+    fn position(&self) -> AppResult<u32> {
+        let query = format!(
+            "SELECT COUNT(*) FROM {0} WHERE listId = ?1 AND rowid < (SELECT rowid FROM {0} WHERE id = ?2 AND listId = ?1)",
+            Self::table_name()
+        );
+        let position = open_connection()?.query_row(&query, (&self.list_id, &self.id), |row| row.get(0))?;
+        Ok(position)
+    }
+
+    /// removes the lead and keeps the counters of its list in line
+    pub fn delete(&self, list: &mut List) -> AppResult<()> {
+        self.delete_with(&open_connection()?, list)
+    }
+
+    fn delete_with(&self, connection: &Connection, list: &mut List) -> AppResult<()> {
+        //leads before the email cursor were already checked, shift the cursor back
+        let already_checked = self.position()? < list.emails_fetched;
+        connection.execute(
+            format!("DELETE FROM {} WHERE id = ?1 AND listId = ?2", Self::table_name()).as_str(),
+            (&self.id, &self.list_id),
+        )?;
+        list.update_meta_with(
+            connection,
+            None,
+            Some(list.leads_fetched.saturating_sub(1)),
+            already_checked.then(|| list.emails_fetched.saturating_sub(1)),
+        )
+    }
+
+    /// moves the lead to the end of another list, in one transaction so it is never in both or neither
+    pub fn move_to(mut self, from: &mut List, to: &mut List) -> AppResult<()> {
+        let mut connection = open_connection()?;
+        let transaction = connection.transaction()?;
+        self.delete_with(&transaction, from)?;
+        self.list_id = to.id;
+        self.insert_with(&transaction)?;
+        to.update_meta_with(&transaction, None, Some(to.leads_fetched + 1), None)?;
+        transaction.commit()?;
+        Ok(())
+    }
 }
 
 impl DBentity for Lead {
@@ -156,30 +450,48 @@ impl DBentity for Lead {
         "leads"
     }
 
-    fn new(args: &rusqlite::Row) -> Self {
-        Self {
-            id: args.get(0).unwrap(),
-            first_name: args.get(1).unwrap(),
-            last_name: args.get(2).unwrap(),
-            name: args.get(3).unwrap(),
-            title: args.get(4).unwrap(),
-            linkedin_url: args.get(5).unwrap(),
-            state: args.get(6).unwrap(),
-            city: args.get(7).unwrap(),
-            org_website: args.get(8).unwrap(),
-            country: args.get(9).unwrap(),
-            org_name: args.get(10).unwrap(),
-            org_fb_url: args.get(11).unwrap(),
-            org_linkedin_url: args.get(12).unwrap(),
-            email: args.get(13).unwrap(),
-            list_id: args.get(14).unwrap(),
-        }
+    fn new(args: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        Ok(Self {
+            id: args.get(0)?,
+            first_name: args.get(1)?,
+            last_name: args.get(2)?,
+            name: args.get(3)?,
+            title: args.get(4)?,
+            linkedin_url: args.get(5)?,
+            state: args.get(6)?,
+            city: args.get(7)?,
+            org_website: args.get(8)?,
+            country: args.get(9)?,
+            org_name: args.get(10)?,
+            org_fb_url: args.get(11)?,
+            org_linkedin_url: args.get(12)?,
+            email: args.get(13)?,
+            list_id: args.get(14)?,
+            email_confidence: args
+                .get::<_, Option<String>>("emailConfidence")?
+                .as_deref()
+                .and_then(EmailConfidence::from_str),
+            email_kind: args
+                .get::<_, Option<String>>("emailKind")?
+                .as_deref()
+                .and_then(EmailKind::from_str),
+        })
     }
 
     fn insert_new(&self) -> Result<usize, rusqlite::Error> {
-        open_connection().execute(
-        format!("INSERT INTO {} (id, first_name, last_name, name, title, linkedin_url, state, city, org_website, country, org_name, org_fb_url, org_linkedin_url, email, listId) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)", Self::table_name()).as_str(),
-        (&self.id, &self.first_name, &self.last_name, &self.name, &self.title, &self.linkedin_url, &self.state, &self.city, &self.org_website, &self.country, &self.org_name, &self.org_fb_url, &self.org_linkedin_url, &self.email, &self.list_id))
+        self.insert_with(&open_connection()?)
+    }
+}
+
+impl Lead {
+    /// inserts the lead on an open connection, so a batch can share one transaction
+    pub fn insert_with(&self, connection: &Connection) -> Result<usize, rusqlite::Error> {
+        let org_id = organizations::link(connection, self)?;
+        let domain = self.org_website.as_deref().and_then(domain::normalize);
+        let kind = self.email.as_deref().and_then(classify::kind).map(|kind| kind.to_str());
+        connection.execute(
+        format!("INSERT INTO {} (id, first_name, last_name, name, title, linkedin_url, state, city, org_website, country, org_name, org_fb_url, org_linkedin_url, email, listId, orgId, domain, emailConfidence, emailKind) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)", Self::table_name()).as_str(),
+        rusqlite::params![&self.id, &self.first_name, &self.last_name, &self.name, &self.title, &self.linkedin_url, &self.state, &self.city, &self.org_website, &self.country, &self.org_name, &self.org_fb_url, &self.org_linkedin_url, &self.email, &self.list_id, org_id, domain, self.email_confidence.map(|c| c.to_str()), kind])
     }
 }
 
@@ -193,6 +505,8 @@ impl Tablular for Lead {
             "state",
             "country",
             "email",
+            "confidence",
+            "kind",
         ]
     }
 
@@ -208,6 +522,11 @@ impl Tablular for Lead {
             self.state.clone().unwrap_or("not available".to_string()),
             self.country.clone(),
             self.email.clone().unwrap_or("not available".to_string()),
+            match self.email {
+                Some(_) => confidence_label(self.email_confidence),
+                None => String::new(),
+            },
+            kind_label(self.email_kind),
         ]
     }
 }
@@ -223,139 +542,81 @@ struct LeadsApiResult {
 }
 
 impl LeadsApiResult {
-    fn insert_all(&self) {
-        self.people.iter().for_each(|person| {
-            let insertion = person.insert_new();
-            match insertion {
-                Err(e) => {
-                    println!("{}: {}", "couldn't insert leads into DB ".red(), e);
-                    panic!()
-                }
-                _ => (),
-            }
-        });
+    /// inserts the page and moves the list past it in one transaction,
+    /// so a failure never leaves leads the cursor would fetch again
+    fn insert_all(&self, list: &mut List) -> AppResult<()> {
+        let mut connection = open_connection()?;
+        let transaction = connection.transaction()?;
+        for person in self.people.iter() {
+            person.insert_with(&transaction)?;
+        }
+        list.update_meta_with(
+            &transaction,
+            self.next.clone(),
+            Some(list.leads_fetched + self.total),
+            None,
+        )?;
+        transaction.commit()?;
+        Ok(())
     }
 }
 
-fn leads_fetcher_from_api(
-    list: &mut List,
-    count: u32,
-    client: Client,
-    mut fetched_count: u32,
-    connection: Connection,
-    api_key_config: &mut ApiKeyRotation,
-    mut url: Url,
-) -> MenuState {
+fn leads_fetcher_from_api(list: &mut List, provider: &ProviderClient, reporter: &Reporter) -> MenuState {
     //1. send api request
     //2. update pointer of the list, in the db as well
     //3. save data to db, stop if needed
     //4. if needs more, return a recursive call with fetchedCount
 
-    if list.leads_fetched > 0 && matches!(list.next_pointer, None) {
-        println!(
-            "{}",
-            "cannot fetch leads, no more data available to fetch".red()
-        )
+    if list.leads_fetched > 0 && list.next_pointer.is_none() {
+        reporter.error("cannot fetch leads, no more data available to fetch")
     }
 
-    //next pointer
-    make_url_with_next(&mut url, &list);
-    println!("{}", url);
-    let resp = client
-        .get(url.clone())
-        .headers(header_constructor(&api_key_config, "apollo-api-pro.p.rapidapi.com"))
-        .send();
-    match resp {
+    //url with the next pointer
+    let resp = url_parser(list).and_then(|mut url| {
+        make_url_with_next(&mut url, list);
+        provider.get_json::<LeadsApiResult>(&url)
+    });
+    let mut deserialized_resp = match resp {
+        Ok(resp) => resp,
         Err(e) => {
-            println!("{}: {}", "something went wrong".red(), e.to_string().red());
-            return MenuState::FetchLeads;
-        }
-        Ok(ref r) if r.status() == 429 => {
-            println!("{}", "api key limit reached".red());
-            println!("{}", "rotating api key...".blue());
-            let rotation_result = api_key_config.rotate();
-            match rotation_result {
-                Err(e) => {
-                    println!("{}", e);
-                   return MenuState::FetchLeads;
-                }
-                Ok(_) => {
-                    println!("{}", "successfuly rotated API key".blue());
-                    return leads_fetcher_from_api(
-                        list,
-                        count,
-                        client,
-                        fetched_count,
-                        connection,
-                        api_key_config,
-                        url,
-                    );
-                }
-            }
-        }
-        Ok( r) if !r.status().is_success() => {
-            println!("{}: {}", "something went wrong".red(), r.text().unwrap_or("".to_string()));
+            reporter.error(format!("something went wrong: {}", e));
             return MenuState::FetchLeads;
         }
-        Ok(_) => {}
-    }
-
-    //parsing
-    let deserialized_resp_maybe = resp.unwrap().json::<LeadsApiResult>();
-    if let Err(e) = &deserialized_resp_maybe {
-        println!("{}: {}", "Failed to deserialize response".red(), e);
-        return MenuState::FetchLeads;
-    }
-
-    let mut deserialized_resp: LeadsApiResult = deserialized_resp_maybe.unwrap();
+    };
     deserialized_resp
         .people
         .iter_mut()
         .for_each(|person| person.list_id = list.id.to_owned());
-    deserialized_resp.insert_all();
-
-    fetched_count += deserialized_resp.total;
-    //update the next pointer and leadsFetched, pass the next pointer or stop
-    let update_result = list.update_meta(
-        deserialized_resp.next,
-        Some(&list.leads_fetched + deserialized_resp.total),
-        None,
-    );
-    match update_result {
-        Err(e) => {
-            println!("{}", e);
-            return MenuState::FetchLeads;
-        }
-        Ok(_) => println!("{}", "successfully update the meta".blue()),
+    //the leads, the next pointer and leadsFetched are saved together
+    if let Err(e) = deserialized_resp.insert_all(list) {
+        reporter.error(format!("couldn't insert leads into DB: {}", e));
+        return MenuState::FetchLeads;
     }
+    reporter.info("successfully update the meta");
+
+    let mut progress = provider.progress();
+    progress.done += deserialized_resp.total;
+    reporter.progress(&progress);
 
-    if fetched_count >= count {
-        println!(
+    if progress.done >= progress.target {
+        reporter.info(format!(
             "successfuly fetched {} leads for the list {}",
-            fetched_count, list.name
-        );
+            progress.done, list.name
+        ));
         return MenuState::Main;
     }
-    return leads_fetcher_from_api(
-        list,
-        count,
-        client,
-        fetched_count,
-        connection,
-        api_key_config,
-        url,
-    );
+    drop(progress);
+    leads_fetcher_from_api(list, provider, reporter)
 }
 
-fn url_parser(list: &List) -> Url {
-    let mut base_url_text = "https://www.apollo-api-pro.p.rapidapi.com".to_string();
+fn url_parser(list: &List) -> AppResult<Url> {
+    let mut base_url_text = format!("https://www.{}", LEADS_PROVIDER.host());
     let ListFilter {
         person_title,
         location,
         industry,
         employee_size,
         keywords,
-        ..
     } = &list.filter;
     base_url_text = format!(
         "{}/page?locations={}&industry={}&personTitle={}",
@@ -372,8 +633,7 @@ fn url_parser(list: &List) -> Url {
         base_url_text = format!("{}&qKeywords={}", base_url_text, keywords);
     };
 
-    let base_url = Url::parse(base_url_text.as_str()).unwrap();
-    base_url
+    Ok(Url::parse(base_url_text.as_str())?)
 }
 
 fn make_url_with_next(url: &mut Url, list: &List) {
@@ -388,15 +648,14 @@ fn make_url_with_next(url: &mut Url, list: &List) {
     }
 }
 
-pub fn header_constructor(api_key_config: &ApiKeyRotation, api_host: &str) -> HeaderMap {
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        "x-rapidapi-key",
-        HeaderValue::from_str(api_key_config.get().key.as_str()).unwrap(),
-    );
-    headers.insert(
-        "x-rapidapi-host",
-        HeaderValue::from_str(api_host).unwrap() 
-    );
-    headers
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_counts_characters() {
+        assert_eq!(truncate("Directrice générale", 12), "Directrice g...");
+        assert_eq!(truncate("Directeur général", 12), "Directeur gé...");
+        assert_eq!(truncate("CEO", 12), "CEO");
+    }
 }
@@ -6,9 +6,11 @@ use crate::{
     MenuState,
     db::{DBentity, Tablular, list_all, open_connection, tabular_output},
     empty_validator,
+    error::{AppError, AppResult},
+    import::import_csv,
 };
-use anyhow::{anyhow};
 use colorize::AnsiColor;
+use rusqlite::Connection;
 use inquire::{list_option::ListOption, validator::{ Validation}, MultiSelect, Select, Text};
 
 #[derive(Clone)]
@@ -18,7 +20,7 @@ pub enum EmployeeSize {
 }
 
 pub fn empty_size_validator(v: &[ListOption<&&str>]) -> Result<Validation, Box<dyn Error + Send + Sync>> {
-    if v.len() == 0 {
+    if v.is_empty() {
         Ok(Validation::Invalid("please select an option".into()))
     }else{
         Ok(Validation::Valid)
@@ -26,7 +28,7 @@ pub fn empty_size_validator(v: &[ListOption<&&str>]) -> Result<Validation, Box<d
 }
 
 #[derive(Clone)]
-enum SizeOptions {
+pub enum SizeOptions {
     Under10,
     Under20,
     Under50,
@@ -93,23 +95,23 @@ impl EmployeeSize {
         }
     }
 
-   pub fn from_str(text: String, from_api: bool) -> EmployeeSize {
+   pub fn from_str(text: String, from_api: bool) -> AppResult<EmployeeSize> {
         let sep = if from_api { ";" } else { ", " };
         match text.as_str() {
-            "unspecified" => EmployeeSize::Unspecified,
-            text => EmployeeSize::Specified(
+            "unspecified" => Ok(EmployeeSize::Unspecified),
+            text => Ok(EmployeeSize::Specified(
                 text.split(sep)
-                    .into_iter()
-                    .map(|single_filter: &str| SizeOptions::from_str(single_filter).unwrap())
-                    .collect::<Vec<SizeOptions>>(),
-            ),
+                    .map(|single_filter: &str| {
+                        SizeOptions::from_str(single_filter)
+                            .ok_or_else(|| AppError::Parse(format!("employee size {}", single_filter)))
+                    })
+                    .collect::<AppResult<Vec<SizeOptions>>>()?,
+            )),
         }
     }
 }
 #[derive(Clone)]
 pub struct ListFilter {
-    id: u32,
-    name: String,
    pub person_title: String,
    pub location: String,
    pub  industry: String,
@@ -167,28 +169,36 @@ impl DBentity for List {
         Some("SELECT * FROM lists INNER JOIN filters ON lists.filter = filters.id")
     }
 
-    fn new(row: &rusqlite::Row) -> Self {
-        Self {
-            id: row.get(0).unwrap(),
-            name: row.get(1).unwrap(),
-            leads_fetched: row.get(2).unwrap(),
-            emails_fetched: row.get(3).unwrap(),
-            next_pointer: row.get(5).unwrap(),
+    fn new(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        let employee_size = EmployeeSize::from_str(row.get(12)?, false).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(12, rusqlite::types::Type::Text, Box::new(e))
+        })?;
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            leads_fetched: row.get(2)?,
+            emails_fetched: row.get(3)?,
+            next_pointer: row.get(5)?,
             filter: ListFilter {
-                id: row.get(6).unwrap(),
-                name: row.get(7).unwrap(),
-                person_title: row.get(8).unwrap(),
-                location: row.get(9).unwrap(),
-                industry: row.get(10).unwrap(),
-                keywords: row.get(11).unwrap(),
-                employee_size: EmployeeSize::from_str(row.get(12).unwrap(), false),
+                person_title: row.get(8)?,
+                location: row.get(9)?,
+                industry: row.get(10)?,
+                keywords: row.get(11)?,
+                employee_size,
             },
-        }
+        })
     }
 
     fn insert_new(&self) -> Result<usize, rusqlite::Error> {
         //supposed to just run the insert query
-        let connection = open_connection();
+        self.insert_with(&open_connection()?)?;
+        Ok(1)
+    }
+}
+
+impl List {
+    /// inserts the list and its filter on an open connection, returning the id of the new list
+    pub fn insert_with(&self, connection: &Connection) -> Result<u32, rusqlite::Error> {
         connection.execute("INSERT INTO filters (name, person, location, industry, keywords, employeeSize) VALUES(?1, ?2, ?3, ?4, ?5, ?6)", 
         (format!("{}-filter", &self.name), &self.filter.person_title, &self.filter.location, &self.filter.industry,&self.filter.keywords, &self.filter.employee_size.to_str(false))
     )?;
@@ -196,7 +206,7 @@ impl DBentity for List {
         let primaryid = connection.last_insert_rowid();
         connection.execute(
             format!(
-                "INSERT INTO {} (name, leadsFetched, emailsFetched, filter) VALUES (?1, ?2, ?3, ?4)",
+                "INSERT INTO {} (name, leadsFetched, emailsFetched, filter, next) VALUES (?1, ?2, ?3, ?4, ?5)",
                 Self::table_name()
             )
             .as_str(),
@@ -205,22 +215,24 @@ impl DBentity for List {
                 &self.leads_fetched,
                 &self.emails_fetched,
                 primaryid,
+                &self.next_pointer,
             ),
-        )
+        )?;
+        Ok(connection.last_insert_rowid() as u32)
     }
 }
 
-pub fn list_handler() -> MenuState {
+pub fn list_handler() -> AppResult<MenuState> {
 
-    let data = list_all::<List>(List::custom_query().map(|v|{v.to_string()}), None).unwrap().items;
-    let options = vec!["List all lists".blue(), "Add new".blue(), "Back".red()];
+    let data = list_all::<List>(List::custom_query().map(|v|{v.to_string()}), None)?.items;
+    let options = vec!["List all lists".blue(), "Add new".blue(), "Import leads from CSV".blue(), "Back".red()];
     let direction = Select::new("Lists".green().as_str(), options.clone())
-        .prompt()
-        .unwrap();
+        .prompt()?;
     match options.iter().position(|x| *x == direction) {
-        Some(0) => list_all_lists(&data),
+        Some(0) => Ok(list_all_lists(&data)),
         Some(1) => add_new_list(data),
-        _ => MenuState::Settings,
+        Some(2) => import_csv(),
+        _ => Ok(MenuState::Settings),
     }
 }
 
@@ -229,7 +241,7 @@ fn list_all_lists(data: &Vec<List>) -> MenuState {
     MenuState::Lists
 }
 
-fn add_new_list(data: Vec<List>) -> MenuState {
+pub fn add_new_list(data: Vec<List>) -> AppResult<MenuState> {
     let dup_validator_list = move |val: &str| {
         let names: Vec<String> = data
             .iter()
@@ -247,36 +259,31 @@ fn add_new_list(data: Vec<List>) -> MenuState {
     //name
     let name = Text::new("enter the name of the new list:".blue().as_str())
         .with_validators(&[Box::new(dup_validator_list), Box::new(empty_validator)])
-        .prompt()
-        .unwrap()
+        .prompt()?
         .trim()
         .to_string();
     //person
     let person = Text::new("enter the title of the person:".blue().as_str())
         .with_validator(empty_validator)
         .with_placeholder("ceo")
-        .prompt()
-        .unwrap()
+        .prompt()?
         .trim()
         .to_string();
     let location = Text::new("enter the location:".blue().as_str())
         .with_placeholder("Texas")
         .with_validator(empty_validator)
-        .prompt()
-        .unwrap()
+        .prompt()?
         .trim()
         .to_string();
     let industry = Text::new("enter the industry name:".blue().as_str())
         .with_placeholder("construction")
         .with_validator(empty_validator)
-        .prompt()
-        .unwrap()
+        .prompt()?
         .trim()
         .to_string();
     let keywords_in = Text::new("enter the keywords:".blue().as_str())
         .with_placeholder("roofing")
-        .prompt()
-        .unwrap()
+        .prompt()?
         .trim()
         .to_string();
     let keywords = match keywords_in.as_str() {
@@ -305,12 +312,11 @@ fn add_new_list(data: Vec<List>) -> MenuState {
         options.clone(),
     )
     .with_validator(Box::new(&empty_size_validator))
-    .prompt()
-    .unwrap();
+    .prompt()?;
     let employee_size = if selected_sizes.contains(&"unspecified") {
         EmployeeSize::Unspecified
     } else {
-        EmployeeSize::from_str(selected_sizes.join(", "), false)
+        EmployeeSize::from_str(selected_sizes.join(", "), false)?
     };
 
     let newlist = List {
@@ -320,8 +326,6 @@ fn add_new_list(data: Vec<List>) -> MenuState {
         leads_fetched: 0,
         emails_fetched: 0,
         filter: ListFilter {
-            id: 1,
-            name: format!("{}-filter", name),
             person_title: person,
             location,
             industry,
@@ -335,12 +339,23 @@ fn add_new_list(data: Vec<List>) -> MenuState {
         Err(e) => println!("{} error: {:?} \n", "couldn't add the new list".red(), e),
     }
 
-    MenuState::Lists
+    Ok(MenuState::Lists)
 }
 
 
 impl List {
-    pub fn update_meta(&mut self, next_pointer: Option<String>, leads_fetched: Option<u32>, emails_fetched: Option<u32>) -> anyhow::Result<()> {
+    pub fn update_meta(&mut self, next_pointer: Option<String>, leads_fetched: Option<u32>, emails_fetched: Option<u32>) -> AppResult<()> {
+        self.update_meta_with(&open_connection()?, next_pointer, leads_fetched, emails_fetched)
+    }
+
+    /// like `update_meta`, on an open connection so it can share a transaction with the leads it counts
+    pub fn update_meta_with(
+        &mut self,
+        connection: &Connection,
+        next_pointer: Option<String>,
+        leads_fetched: Option<u32>,
+        emails_fetched: Option<u32>,
+    ) -> AppResult<()> {
         let mut query = format!("UPDATE {} SET ", List::table_name());
         let mut changes = Vec::with_capacity(3);
         if let Some(next) = next_pointer {
@@ -356,12 +371,12 @@ impl List {
             self.emails_fetched = emails_fetched
         };
 
-        if changes.len() == 0 {
-           return Err(anyhow!("no changes to make"));
+        if changes.is_empty() {
+           return Err(AppError::Other("no changes to make".to_string()));
         };
         query.push_str(changes.join(" , ").as_str());
         query.push_str(format!(" WHERE id = {}", self.id).as_str());
-       _ = open_connection().execute(query.as_str(), [])?;
+       _ = connection.execute(query.as_str(), [])?;
        Ok(())
 
     }
@@ -0,0 +1,182 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        LazyLock, Mutex,
+    },
+};
+
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::{config, jobs::lock};
+
+/// strings that must never reach the log, with what to write instead
+static SECRETS: LazyLock<Mutex<Vec<(String, String)>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// whether `--verbose` was passed, it keeps the level at debug or above
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// `log.max_size_kb` in bytes and `log.max_files`, they can change once the database is open
+static MAX_SIZE: AtomicU64 = AtomicU64::new(1024 * 1024);
+static MAX_FILES: AtomicU32 = AtomicU32::new(3);
+
+/// hides `secret` behind `masked` in every line logged from now on
+pub fn add_secret(secret: &str, masked: String) {
+    if secret.is_empty() {
+        return;
+    }
+    let mut secrets = lock(&SECRETS);
+    if !secrets.iter().any(|(known, _)| known == secret) {
+        secrets.push((secret.to_string(), masked));
+    }
+}
+
+pub fn redact(text: &str) -> String {
+    lock(&SECRETS)
+        .iter()
+        .fold(text.to_string(), |text, (secret, masked)| text.replace(secret, masked))
+}
+
+/// writes to apollo.log, rotating it once it grows past `log.max_size_kb`
+struct FileLogger {
+    path: PathBuf,
+    file: Mutex<Option<LogFile>>,
+    echo: bool,
+}
+
+/// the open apollo.log and its size, counted as lines are written instead of asked for each line
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} {} {}\n",
+            Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+            record.level(),
+            record.target(),
+            redact(&record.args().to_string())
+        );
+        if self.echo {
+            eprint!("{}", line);
+        }
+        let mut file = lock(&self.file);
+        self.rotate_if_needed(&mut file);
+        if let Some(log_file) = file.as_mut()
+            && log_file.file.write_all(line.as_bytes()).is_ok()
+        {
+            log_file.size += line.len() as u64;
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(log_file) = lock(&self.file).as_mut() {
+            _ = log_file.file.flush();
+        }
+    }
+}
+
+impl FileLogger {
+    fn open(&self) -> Option<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path).ok()?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Some(LogFile { file, size })
+    }
+
+    fn rotated(&self, index: u32) -> PathBuf {
+        PathBuf::from(format!("{}.{}", self.path.display(), index))
+    }
+
+    /// apollo.log becomes apollo.log.1, apollo.log.1 becomes apollo.log.2 and so on,
+    /// the oldest file past `log.max_files` is dropped
+    fn rotate_if_needed(&self, file: &mut Option<LogFile>) {
+        if file.is_none() {
+            *file = self.open();
+        }
+        let size = file.as_ref().map(|log_file| log_file.size).unwrap_or(0);
+        if size < MAX_SIZE.load(Ordering::Relaxed) {
+            return;
+        }
+        *file = None;
+        let max_files = MAX_FILES.load(Ordering::Relaxed);
+        if max_files == 0 {
+            _ = fs::remove_file(&self.path);
+        } else {
+            _ = fs::remove_file(self.rotated(max_files));
+            for index in (1..max_files).rev() {
+                _ = fs::rename(self.rotated(index), self.rotated(index + 1));
+            }
+            _ = fs::rename(&self.path, self.rotated(1));
+        }
+        *file = self.open();
+    }
+}
+
+/// sends log records to apollo.log in `data_dir`, `echo` copies them to stderr as well
+pub fn init(data_dir: &Path, verbose: bool, echo: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+    let logger = FileLogger {
+        path: data_dir.join("apollo.log"),
+        file: Mutex::new(None),
+        echo,
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        apply_settings();
+    }
+}
+
+/// applies the `log.*` settings, also after they changed
+pub fn apply_settings() {
+    MAX_SIZE.store(config::get_u64("log.max_size_kb").max(1) * 1024, Ordering::Relaxed);
+    MAX_FILES.store(config::get_u64("log.max_files") as u32, Ordering::Relaxed);
+    let level = match config::get("log.level").as_str() {
+        "off" => LevelFilter::Off,
+        "error" => LevelFilter::Error,
+        "warn" => LevelFilter::Warn,
+        "debug" => LevelFilter::Debug,
+        "trace" => LevelFilter::Trace,
+        _ => LevelFilter::Info,
+    };
+    if VERBOSE.load(Ordering::Relaxed) {
+        log::set_max_level(level.max(LevelFilter::Debug));
+    } else {
+        log::set_max_level(level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn rotates_on_the_bytes_written() {
+        let dir = env::temp_dir().join(format!("apollo-log-{}", fastrand::u64(..)));
+        fs::create_dir_all(&dir).unwrap();
+        let logger = FileLogger { path: dir.join("apollo.log"), file: Mutex::new(None), echo: false };
+        MAX_SIZE.store(100, Ordering::Relaxed);
+        log::set_max_level(LevelFilter::Info);
+        for _ in 0..3 {
+            logger.log(&Record::builder().level(Level::Info).args(format_args!("{}", "x".repeat(40))).build());
+        }
+        //two lines fill the file, the third starts a new one
+        assert!(logger.rotated(1).is_file());
+        assert_eq!(lock(&logger.file).as_ref().map(|log_file| log_file.size), fs::metadata(&logger.path).ok().map(|m| m.len()));
+        _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    error::Error, fs
+    env, error::Error, fs, io::{self, IsTerminal}
 };
 mod apikey;
 mod db;
@@ -10,11 +10,31 @@ mod fetch;
 mod emails;
 mod viewleads;
 mod startup;
+mod jobs;
+mod progress;
+mod config;
+mod retry;
+mod ratelimit;
+mod http;
+mod tui;
+mod error;
+mod logging;
+mod traffic;
+mod import;
+mod bundle;
+mod profile;
+mod backup;
+mod organizations;
+mod domain;
+mod dns;
+mod smtp;
+mod classify;
 use colorize::AnsiColor;
 use directories::BaseDirs;
-use inquire::{validator::Validation, Select};
-use settings::main_settings;
-use startup::{sqlite_init};
+use bundle::export_handler;
+use error::{AppError, AppResult};
+use inquire::{validator::Validation, Select, Text};
+use settings::{general_settings, main_settings, network_settings};
 
 use crate::{apikey::api_key_handler, emails::fetch_emails, fetch::main_fetch, leads::fetch_leads, list::list_handler, viewleads::view_leads};
 
@@ -32,41 +52,69 @@ fn clear_and_logo(heading: String) {
          dP"
         .green()
     );
+    println!("{} {}\n", "workspace:".blue(), profile::active());
     println!("{}\n", heading.blink().bold().blue())
 }
 
 fn main() {
     //check for config dir
     let mut first_time = false;
-    let datadir = BaseDirs::new().unwrap();
+    let Some(datadir) = BaseDirs::new() else {
+        println!("{}", "couldn't find your home directory, apollo needs it to store its data".red());
+        return;
+    };
     let mut state = MenuState::Main;
-    let mut datadirlocal = datadir.data_local_dir().join("apollo");
+    let datadirlocal = datadir.data_local_dir().join("apollo");
 
-    if !fs::exists(&datadirlocal).unwrap() {
+    if !datadirlocal.exists() {
         first_time = true;
-        _ = fs::create_dir(&datadirlocal);
+        if let Err(e) = fs::create_dir_all(&datadirlocal) {
+            println!("{} {}: {}", "couldn't create the data directory".red(), datadirlocal.display(), e);
+            return;
+        }
+    }
+
+    //settings from the config file, the environment and --set win over the stored ones
+    let args = env::args().collect::<Vec<String>>();
+    let config_warnings = config::load(&args);
+    for warning in config_warnings.iter() {
+        println!("{} {}", "ignored setting".red(), warning);
+    }
+
+    //the full-screen UI would be garbled by log lines on the console
+    let classic = env::args().any(|arg| arg == "--classic") || !io::stdout().is_terminal();
+    let verbose = env::args().any(|arg| arg == "--verbose");
+    jobs::set_dry_run(env::args().any(|arg| arg == "--dry-run"));
+    traffic::set(traffic::from_args(&args, &datadirlocal));
+    //before the database is opened, so its migrations are logged too
+    logging::init(&datadirlocal, verbose, verbose && classic);
+    for warning in config_warnings {
+        log::warn!("ignored setting {}", warning);
     }
 
-    //make the sqlite if not already there
-    datadirlocal = datadirlocal.join("apollo.sqlite");
-    if !fs::exists(&datadirlocal).unwrap() {
-        sqlite_init(&datadirlocal);
+    //make the sqlite of the profile if not already there, also adds tables missing from older databases
+    let opened = profile::from_args(&args).and_then(|name| profile::open(&name));
+    match opened {
+        Ok(created) => first_time |= created,
+        Err(e) => {
+            log::error!("couldn't open the database: {}", e);
+            println!("{} {}", "couldn't open the database:".red(), e);
+            return;
+        }
     }
+    log::info!("apollo {} started profile={}", env!("CARGO_PKG_VERSION"), profile::active());
 
 
-    loop {
-        state = match state {
-            MenuState::Main => main_menu(first_time),
-            MenuState::Fetch => main_fetch(),
-            MenuState::Settings => main_settings(),
-            MenuState::APIkeys => api_key_handler(),
-            MenuState::FetchEmails => fetch_emails(),
-            MenuState::Lists => list_handler(),
-            MenuState::FetchLeads => fetch_leads(),
-            MenuState::ViewLeads => view_leads(),
-            MenuState::GoodBye => break,
-            _ => break,
+    //the full-screen UI needs a terminal, fall back to the prompts otherwise
+    if classic {
+        loop {
+            state = match state {
+                MenuState::GoodBye => break,
+                state => handle_state(state, first_time),
+            }
         }
+    } else {
+        tui::run(first_time);
     }
 
     println!("{}", r"                                 dP dP                                                       dP dP          
@@ -79,16 +127,73 @@ fn main() {
  d8888P                                       d8888P                       dP".green())
 }
 
-enum MenuState {
+/// runs the prompt based screen of a state and returns the state to go to next
+/// errors are shown to the user, who is then taken back to the previous screen
+pub fn handle_state(state: MenuState, first_time: bool) -> MenuState {
+    match run_state(state, first_time) {
+        Ok(next) => next,
+        //esc on the main menu means leaving
+        Err(AppError::Cancelled) if state == MenuState::Main => MenuState::GoodBye,
+        Err(AppError::Cancelled) => state.parent(),
+        Err(e) => {
+            log::error!("{}", e);
+            println!("{}", e.to_string().red());
+            //keep the message on screen until the user read it
+            _ = Text::new("press enter to continue").prompt();
+            state.parent()
+        }
+    }
+}
+
+fn run_state(state: MenuState, first_time: bool) -> AppResult<MenuState> {
+    match state {
+        MenuState::Main => main_menu(first_time),
+        MenuState::Fetch => main_fetch(),
+        MenuState::Settings => main_settings(),
+        MenuState::GeneralSettings => general_settings(),
+        MenuState::NetworkSettings => network_settings(),
+        MenuState::APIkeys => api_key_handler(),
+        MenuState::FetchEmails => fetch_emails(),
+        MenuState::Lists => list_handler(),
+        MenuState::FetchLeads => fetch_leads(),
+        MenuState::ViewLeads => view_leads(),
+        MenuState::Export => export_handler(),
+        MenuState::Profiles => profile::switch_profile(),
+        MenuState::Database => backup::database_handler(),
+        MenuState::Companies => organizations::view_companies(),
+        MenuState::GoodBye => Ok(MenuState::GoodBye),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MenuState {
     Main,
     Settings,
+    GeneralSettings,
+    NetworkSettings,
     APIkeys,
     GoodBye,
     Lists,
     Fetch,
     FetchLeads,
     FetchEmails,
-    ViewLeads
+    ViewLeads,
+    Export,
+    Profiles,
+    Database,
+    Companies,
+}
+
+impl MenuState {
+    /// the screen a user comes from when reaching this one
+    pub fn parent(self) -> MenuState {
+        match self {
+            Self::Main | Self::GoodBye => self,
+            Self::Settings | Self::Fetch | Self::ViewLeads | Self::Export | Self::Profiles | Self::Companies => Self::Main,
+            Self::GeneralSettings | Self::NetworkSettings | Self::APIkeys | Self::Lists | Self::Database => Self::Settings,
+            Self::FetchLeads | Self::FetchEmails => Self::Fetch,
+        }
+    }
 }
 
     pub fn empty_validator(v: &str) ->Result<Validation, Box<dyn Error + Send + Sync>> {
@@ -99,20 +204,29 @@ enum MenuState {
         }
     }
 
-fn main_menu(first_time: bool) -> MenuState {
+fn main_menu(first_time: bool) -> AppResult<MenuState> {
     clear_and_logo("Main Menu".to_string());
     if first_time {
         println!("{}","This is your first time using Apollo, please consider setting an API key first.".blue())
     }
-    let options = vec!["Fetch", "Settings", "Export", "View Leads", "Exit"];
+    if jobs::dry_run() {
+        println!("{}", "dry run: fetches only show what they would send, nothing is sent or saved.".blue())
+    }
+    match traffic::mode() {
+        traffic::Traffic::Record(dir) => println!("{} {}", "recording provider responses to".blue(), dir.display()),
+        traffic::Traffic::Replay(dir) => println!("{} {}", "replaying provider responses from".blue(), dir.display()),
+        traffic::Traffic::Live => {}
+    }
+    let options = vec!["Fetch", "Settings", "Export", "View Leads", "View Companies", "Switch workspace", "Exit"];
     let selection = Select::new("Settings".green().as_str(), options.clone())
-        .prompt()
-        .unwrap();
+        .prompt()?;
     match options.iter().position(|&x| x == selection) {
-        Some(0) => MenuState::Fetch,
-        Some(1) => MenuState::Settings,
-        Some(2) => MenuState::Main,
-        Some(3) => MenuState::ViewLeads,
-        _ => MenuState::GoodBye,
+        Some(0) => Ok(MenuState::Fetch),
+        Some(1) => Ok(MenuState::Settings),
+        Some(2) => Ok(MenuState::Export),
+        Some(3) => Ok(MenuState::ViewLeads),
+        Some(4) => Ok(MenuState::Companies),
+        Some(5) => Ok(MenuState::Profiles),
+        _ => Ok(MenuState::GoodBye),
     }
 }
@@ -0,0 +1,326 @@
+use colorize::AnsiColor;
+use inquire::{Select, Text};
+use rusqlite::{Connection, OptionalExtension, Row};
+
+use crate::{
+    clear_and_logo, config,
+    domain,
+    db::{list_all, open_connection, tabular_output, DBentity, PageConfig, Tablular},
+    error::AppResult,
+    leads::{EmailConfidence, Lead, LeadField},
+    MenuState,
+};
+
+/// local parts tried when guessing an address, `{f}` is the first letter of the first name
+pub const PATTERNS: [&str; 3] = ["{last}", "{first}", "{f}{last}"];
+
+/// a company, shared by all its leads and keyed by the domain of its website
+/// the leads keep the organization fields the provider returned, this is where
+/// what we learn about the company is kept
+pub struct Organization {
+    pub id: u32,
+    pub domain: String,
+    pub name: String,
+    website: Option<String>,
+    fb_url: Option<String>,
+    linkedin_url: Option<String>,
+    pub email_pattern: Option<String>,
+    pub notes: Option<String>,
+    /// whether the mail server accepts any address, none until an email was checked there
+    pub catch_all: Option<bool>,
+    leads: u32,
+    emails: u32,
+}
+
+impl DBentity for Organization {
+    fn table_name() -> &'static str {
+        "organizations"
+    }
+
+    /// every company with the number of its leads and of those with an email
+    fn custom_query() -> Option<&'static str> {
+        Some(
+            "SELECT organizations.*, COUNT(leads.rowid), COUNT(leads.email) FROM organizations \
+             LEFT JOIN leads ON leads.orgId = organizations.id GROUP BY organizations.id \
+             ORDER BY COUNT(leads.rowid) DESC, organizations.domain",
+        )
+    }
+
+    fn new(row: &Row) -> Result<Self, rusqlite::Error> {
+        Ok(Self {
+            id: row.get(0)?,
+            domain: row.get(1)?,
+            name: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            website: row.get(3)?,
+            fb_url: row.get(4)?,
+            linkedin_url: row.get(5)?,
+            email_pattern: row.get(6)?,
+            notes: row.get(7)?,
+            catch_all: row.get(8)?,
+            leads: row.get(9)?,
+            emails: row.get(10)?,
+        })
+    }
+
+    fn insert_new(&self) -> Result<usize, rusqlite::Error> {
+        let connection = open_connection()?;
+        upsert(
+            &connection,
+            &self.domain,
+            &self.name,
+            self.website.as_deref(),
+            self.fb_url.as_deref(),
+            self.linkedin_url.as_deref(),
+        )?;
+        Ok(1)
+    }
+}
+
+impl Tablular for Organization {
+    fn headers() -> Vec<&'static str> {
+        vec!["name", "domain", "leads", "emails", "email pattern", "catch-all", "notes"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let notes = self.notes.clone().unwrap_or_default();
+        let last_note = notes.lines().last().unwrap_or_default();
+        vec![
+            self.name.clone(),
+            self.domain.clone(),
+            self.leads.to_string(),
+            self.emails.to_string(),
+            self.email_pattern.clone().unwrap_or("unknown".to_string()),
+            catch_all_label(self.catch_all).to_string(),
+            last_note.chars().take(40).collect(),
+        ]
+    }
+}
+
+fn catch_all_label(catch_all: Option<bool>) -> &'static str {
+    match catch_all {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "unknown",
+    }
+}
+
+/// adds the company if it is new, filling in what it was missing otherwise
+fn upsert(
+    connection: &Connection,
+    domain: &str,
+    name: &str,
+    website: Option<&str>,
+    fb_url: Option<&str>,
+    linkedin_url: Option<&str>,
+) -> Result<u32, rusqlite::Error> {
+    connection.query_row(
+        "INSERT INTO organizations (domain, name, website, fbUrl, linkedinUrl) VALUES (?1, ?2, ?3, ?4, ?5) \
+         ON CONFLICT(domain) DO UPDATE SET \
+         name = COALESCE(NULLIF(organizations.name, ''), excluded.name), \
+         website = COALESCE(organizations.website, excluded.website), \
+         fbUrl = COALESCE(organizations.fbUrl, excluded.fbUrl), \
+         linkedinUrl = COALESCE(organizations.linkedinUrl, excluded.linkedinUrl) \
+         RETURNING id",
+        (domain, name, website, fb_url, linkedin_url),
+        |row| row.get(0),
+    )
+}
+
+/// the company of a lead, none when the lead has no usable website
+pub fn link(connection: &Connection, lead: &Lead) -> Result<Option<u32>, rusqlite::Error> {
+    let Some(domain) = lead.get(LeadField::OrgWebsite).as_deref().and_then(domain::normalize) else {
+        return Ok(None);
+    };
+    let id = upsert(
+        connection,
+        &domain,
+        &lead.get(LeadField::OrgName).unwrap_or_default(),
+        lead.get(LeadField::OrgWebsite).as_deref(),
+        lead.get(LeadField::OrgFacebookUrl).as_deref(),
+        lead.get(LeadField::OrgLinkedinUrl).as_deref(),
+    )?;
+    Ok(Some(id))
+}
+
+/// migration creating the table and linking the leads already stored
+pub fn migrate(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS organizations (id INTEGER PRIMARY KEY AUTOINCREMENT, domain TEXT UNIQUE NOT NULL, name TEXT, website TEXT, fbUrl TEXT, linkedinUrl TEXT, emailPattern TEXT, notes TEXT);
+         ALTER TABLE leads ADD COLUMN orgId INTEGER;
+         CREATE INDEX IF NOT EXISTS leadsOrgId ON leads (orgId);",
+    )?;
+    let leads = connection
+        .prepare(&format!("SELECT rowid, * FROM {} WHERE org_website IS NOT NULL", Lead::table_name()))?
+        .query_map([], |row| {
+            //the lead columns start after the rowid
+            let rowid: i64 = row.get(0)?;
+            let mut lead = Lead::blank(String::new(), 0);
+            lead.set(LeadField::OrgWebsite, row.get(9)?);
+            lead.set(LeadField::OrgName, row.get(11)?);
+            lead.set(LeadField::OrgFacebookUrl, row.get(12)?);
+            lead.set(LeadField::OrgLinkedinUrl, row.get(13)?);
+            Ok((rowid, lead))
+        })?
+        .collect::<Result<Vec<(i64, Lead)>, rusqlite::Error>>()?;
+    for (rowid, lead) in leads {
+        if let Some(org_id) = link(connection, &lead)? {
+            connection.execute(
+                &format!("UPDATE {} SET orgId = ?1 WHERE rowid = ?2", Lead::table_name()),
+                (org_id, rowid),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// the local part a pattern gives for a lead
+/// names are lowercased and lose their spaces and apostrophes, `Mary Ann O'Brien` gives `maryann` and `obrien`
+pub fn apply(pattern: &str, lead: &Lead) -> String {
+    let name = |name: &str| name.to_lowercase().replace(|c: char| c.is_whitespace() || matches!(c, '\'' | '’'), "");
+    let first_name = name(&lead.first_name);
+    let last_name = name(&lead.last_name);
+    let initial = first_name.chars().next().map(String::from).unwrap_or_default();
+    pattern
+        .replace("{first}", &first_name)
+        .replace("{last}", &last_name)
+        .replace("{f}", &initial)
+}
+
+/// the pattern learned for a domain from the emails confirmed so far
+pub fn pattern_for(domain: &str) -> Option<String> {
+    open_connection()
+        .and_then(|connection| {
+            connection
+                .query_row(
+                    &format!("SELECT emailPattern FROM {} WHERE domain = ?1", Organization::table_name()),
+                    [domain],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .optional()
+        })
+        .ok()
+        .flatten()
+        .flatten()
+}
+
+/// whether the mail server of a domain is known to accept any address
+pub fn catch_all(domain: &str) -> Option<bool> {
+    open_connection()
+        .and_then(|connection| {
+            connection
+                .query_row(
+                    &format!("SELECT catchAll FROM {} WHERE domain = ?1", Organization::table_name()),
+                    [domain],
+                    |row| row.get::<_, Option<bool>>(0),
+                )
+                .optional()
+        })
+        .ok()
+        .flatten()
+        .flatten()
+}
+
+/// records what the check of an email said about the company, the notes are left to the user
+/// only verified emails teach a pattern, a catch-all server accepts whatever we guessed
+pub fn learn(lead: &Lead, email: &str, status: &str, confidence: EmailConfidence) -> AppResult<()> {
+    let (Some((local_part, _)), Some(domain)) = (email.split_once('@'), domain::of_email(email)) else {
+        return Ok(());
+    };
+    let pattern = PATTERNS
+        .into_iter()
+        .find(|pattern| apply(pattern, lead) == local_part)
+        .filter(|_| confidence == EmailConfidence::Verified);
+    open_connection()?.execute(
+        &format!(
+            "UPDATE {} SET emailPattern = COALESCE(?1, emailPattern), catchAll = ?2 WHERE domain = ?3",
+            Organization::table_name()
+        ),
+        (pattern, status == "accept_all", domain),
+    )?;
+    Ok(())
+}
+
+fn update_notes(id: u32, notes: &str) -> AppResult<()> {
+    let notes = Some(notes.trim()).filter(|notes| !notes.is_empty());
+    open_connection()?.execute(
+        &format!("UPDATE {} SET notes = ?1 WHERE id = ?2", Organization::table_name()),
+        (notes, id),
+    )?;
+    Ok(())
+}
+
+/// companies with their rollups, a page at a time
+pub fn view_companies() -> AppResult<MenuState> {
+    let page_size = config::get_u64("view.page_size").clamp(1, 1000) as u32;
+    let mut offset = 0;
+    loop {
+        let page = list_all::<Organization>(
+            Organization::custom_query().map(|v| v.to_string()),
+            Some(PageConfig { rows: page_size, offset }),
+        )?;
+        if page.total_rows == 0 {
+            println!("{}", "there are no companies yet, they are added with the leads that have a website".red());
+            return Ok(MenuState::Main);
+        }
+        tabular_output(
+            &page.items,
+            format!("Companies {}-{} of {}", offset + 1, offset + page.rows, page.total_rows),
+        );
+
+        let mut options = vec!["Open company"];
+        if page.next.is_some() {
+            options.push("Next page");
+        }
+        if offset > 0 {
+            options.push("Previous page");
+        }
+        options.push("Back");
+        match Select::new("Companies".green().as_str(), options).prompt()? {
+            "Open company" => {
+                let labels = page
+                    .items
+                    .iter()
+                    .map(|company| format!("{} ({})", company.name, company.domain))
+                    .collect::<Vec<String>>();
+                let picked = Select::new("which company ?", labels).raw_prompt()?;
+                if let Some(company) = page.items.into_iter().nth(picked.index) {
+                    company_detail(company)?;
+                }
+            }
+            "Next page" => offset += page_size,
+            "Previous page" => offset = offset.saturating_sub(page_size),
+            _ => return Ok(MenuState::Main),
+        }
+    }
+}
+
+fn company_detail(company: Organization) -> AppResult<()> {
+    let leads = list_all::<Lead>(
+        Some(format!("SELECT * FROM {} WHERE orgId = {} ORDER BY rowid", Lead::table_name(), company.id)),
+        None,
+    )?
+    .items;
+    tabular_output(&leads, format!("{} ({})", company.name, company.domain));
+    println!("{} {}", "website:".blue(), company.website.clone().unwrap_or("none".to_string()));
+    println!("{} {}", "LinkedIn:".blue(), company.linkedin_url.clone().unwrap_or("none".to_string()));
+    println!("{} {}", "Facebook:".blue(), company.fb_url.clone().unwrap_or("none".to_string()));
+    println!(
+        "{} {}",
+        "email pattern:".blue(),
+        company.email_pattern.clone().unwrap_or("unknown".to_string())
+    );
+    println!("{} {}", "accepts any address:".blue(), catch_all_label(company.catch_all));
+    println!("{}\n{}\n", "notes:".blue(), company.notes.clone().unwrap_or_default());
+
+    let options = vec!["Edit notes", "Back"];
+    if Select::new("Company".green().as_str(), options).prompt()? == "Edit notes" {
+        let notes = Text::new("notes:")
+            .with_initial_value(company.notes.as_deref().unwrap_or_default())
+            .prompt()?;
+        update_notes(company.id, &notes)?;
+        log::info!("company notes changed domain={}", company.domain);
+        clear_and_logo("Companies".to_string());
+        println!("{} \n", "saved successfully".blue());
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{LazyLock, RwLock},
+};
+
+use colorize::AnsiColor;
+use directories::BaseDirs;
+use inquire::{validator::Validation, Select, Text};
+
+use crate::{
+    clear_and_logo, config,
+    error::{AppError, AppResult},
+    jobs, logging,
+    startup::sqlite_init,
+    MenuState,
+};
+
+/// the profile whose database lives at the old `apollo/apollo.sqlite`, so existing data stays where it was
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// the profile every database connection is opened for
+static ACTIVE: LazyLock<RwLock<String>> = LazyLock::new(|| RwLock::new(DEFAULT_PROFILE.to_string()));
+
+/// the profile asked for by `--profile NAME` or `--profile=NAME`, then `APOLLO_PROFILE`
+pub fn from_args(args: &[String]) -> AppResult<String> {
+    let flag = args.iter().enumerate().find_map(|(index, arg)| match arg.strip_prefix("--profile")? {
+        "" => args.get(index + 1).cloned(),
+        name => name.strip_prefix('=').map(String::from),
+    });
+    let name = match flag {
+        Some(name) => name,
+        None => env::var("APOLLO_PROFILE").unwrap_or_default(),
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(DEFAULT_PROFILE.to_string());
+    }
+    if !valid_name(name) {
+        return Err(AppError::Other(format!(
+            "invalid profile name {}, use letters, digits, - and _",
+            name
+        )));
+    }
+    Ok(name.to_string())
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn active() -> String {
+    ACTIVE.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    Some(BaseDirs::new()?.data_local_dir().join("apollo"))
+}
+
+/// the database file of a profile, each one has its own lists, leads, API keys and settings
+pub fn database(name: &str) -> Option<PathBuf> {
+    let data_dir = data_dir()?;
+    match name {
+        DEFAULT_PROFILE => Some(data_dir.join("apollo.sqlite")),
+        name => Some(data_dir.join("profiles").join(name).join("apollo.sqlite")),
+    }
+}
+
+/// every profile with a database, the default one first
+pub fn all() -> Vec<String> {
+    let mut profiles = Vec::new();
+    if let Some(dir) = data_dir()
+        && let Ok(entries) = fs::read_dir(dir.join("profiles"))
+    {
+        profiles = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| valid_name(name) && name != DEFAULT_PROFILE)
+            .collect();
+        profiles.sort();
+    }
+    profiles.insert(0, DEFAULT_PROFILE.to_string());
+    profiles
+}
+
+/// makes a profile the active one, creating its database if needed
+/// returns whether the database is new
+pub fn open(name: &str) -> AppResult<bool> {
+    let path = database(name).ok_or_else(|| AppError::Other("no home directory found".to_string()))?;
+    let created = !path.exists();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    sqlite_init(&path)?;
+    //a database that failed to open leaves the previous profile active
+    *ACTIVE.write().unwrap_or_else(|e| e.into_inner()) = name.to_string();
+    config::use_database();
+    logging::apply_settings();
+    Ok(created)
+}
+
+/// the "switch workspace" menu
+pub fn switch_profile() -> AppResult<MenuState> {
+    if jobs::running() > 0 {
+        println!("{}", "wait for the running fetches to finish before switching workspace".red());
+        return Ok(MenuState::Main);
+    }
+    clear_and_logo("Switch workspace".to_string());
+    let current = active();
+    let profiles = all();
+    let mut options = profiles
+        .iter()
+        .map(|name| match *name == current {
+            true => format!("{} (current)", name),
+            false => name.clone(),
+        })
+        .collect::<Vec<String>>();
+    options.push("Create new".to_string());
+    options.push("Back".to_string());
+    let selected = Select::new("Workspaces".green().as_str(), options).raw_prompt()?;
+
+    let name = match selected.index {
+        index if index < profiles.len() => profiles[index].clone(),
+        index if index == profiles.len() => {
+            let existing = profiles.clone();
+            Text::new("name of the new workspace:".blue().as_str())
+                .with_validator(move |v: &str| {
+                    let v = v.trim();
+                    if !valid_name(v) {
+                        Ok(Validation::Invalid("use letters, digits, - and _".into()))
+                    } else if existing.iter().any(|name| name == v) {
+                        Ok(Validation::Invalid("a workspace by this name already exists".into()))
+                    } else {
+                        Ok(Validation::Valid)
+                    }
+                })
+                .prompt()?
+                .trim()
+                .to_string()
+        }
+        _ => return Ok(MenuState::Main),
+    };
+    if name == current {
+        return Ok(MenuState::Main);
+    }
+    let created = open(&name)?;
+    log::info!("switched workspace from={} to={} created={}", current, name, created);
+    if created {
+        println!("{} {}", "created the workspace".blue(), name);
+    }
+    Ok(MenuState::Main)
+}
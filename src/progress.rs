@@ -0,0 +1,118 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+const BAR_WIDTH: usize = 30;
+
+/// what a fetch is counting
+#[derive(Clone, Copy)]
+pub enum ProgressKind {
+    Leads,
+    Emails,
+}
+
+/// live counters of a running fetch
+#[derive(Clone)]
+pub struct Progress {
+    pub kind: ProgressKind,
+    pub target: u32,
+    pub done: u32,
+    pub found: u32,
+    pub tried: u32,
+    pub api_key: String,
+    started: Instant,
+    requests: VecDeque<Instant>,
+}
+
+impl Progress {
+    pub fn new(kind: ProgressKind, target: u32) -> Self {
+        Self {
+            kind,
+            target,
+            done: 0,
+            found: 0,
+            tried: 0,
+            api_key: String::new(),
+            started: Instant::now(),
+            requests: VecDeque::new(),
+        }
+    }
+
+    /// records a request sent with the given (masked) API key
+    pub fn request(&mut self, api_key: String) {
+        let now = Instant::now();
+        self.api_key = api_key;
+        self.requests.push_back(now);
+        while self
+            .requests
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) > Duration::from_secs(60))
+        {
+            self.requests.pop_front();
+        }
+    }
+
+    pub fn ratio(&self) -> f64 {
+        if self.target == 0 {
+            return 1.0;
+        }
+        (self.done as f64 / self.target as f64).min(1.0)
+    }
+
+    /// requests sent during the last minute, extrapolated while the fetch is younger than that
+    pub fn requests_per_minute(&self) -> u32 {
+        let elapsed = self.started.elapsed();
+        let window = elapsed.min(Duration::from_secs(60)).as_secs_f64();
+        if window < 1.0 {
+            return self.requests.len() as u32;
+        }
+        (self.requests.len() as f64 * 60.0 / window).round() as u32
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        if self.done == 0 || self.done >= self.target {
+            return None;
+        }
+        let per_item = self.started.elapsed().as_secs_f64() / self.done as f64;
+        Some(Duration::from_secs_f64(per_item * (self.target - self.done) as f64))
+    }
+
+    /// counters without the bar, e.g. "12/50 leads  key abcd…wxyz  14 req/min  ETA 2m 10s"
+    pub fn summary(&self) -> String {
+        let mut parts = vec![match self.kind {
+            ProgressKind::Leads => format!("{}/{} leads", self.done, self.target),
+            ProgressKind::Emails => format!(
+                "{}/{} leads checked  {} found / {} tried",
+                self.done, self.target, self.found, self.tried
+            ),
+        }];
+        if !self.api_key.is_empty() {
+            parts.push(format!("key {}", self.api_key));
+        }
+        parts.push(format!("{} req/min", self.requests_per_minute()));
+        if let Some(eta) = self.eta() {
+            parts.push(format!("ETA {}", format_duration(eta)));
+        }
+        parts.join("  ")
+    }
+
+    pub fn bar(&self) -> String {
+        let filled = (self.ratio() * BAR_WIDTH as f64).round() as usize;
+        format!(
+            "[{}{}] {}",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.summary()
+        )
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, (seconds % 3600) / 60),
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::config;
+
+/// token buckets shared by every request of the process, keyed by host or API key
+static BUCKETS: LazyLock<Mutex<HashMap<String, TokenBucket>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// requests allowed per second and per minute, 0 means unlimited
+#[derive(Clone, Copy)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub per_minute: u32,
+}
+
+impl RateLimit {
+    /// reads the `ratelimit.<name>.per_second` and `ratelimit.<name>.per_minute` settings
+    pub fn from_settings(name: &str) -> Self {
+        Self {
+            per_second: config::get_u64(format!("ratelimit.{}.per_second", name).as_str()) as u32,
+            per_minute: config::get_u64(format!("ratelimit.{}.per_minute", name).as_str()) as u32,
+        }
+    }
+
+    /// one bucket per window that is limited
+    fn buckets(&self) -> Vec<(&'static str, f64, f64)> {
+        let mut buckets = Vec::new();
+        if self.per_second > 0 {
+            buckets.push(("second", self.per_second as f64, self.per_second as f64));
+        }
+        if self.per_minute > 0 {
+            buckets.push(("minute", self.per_minute as f64, self.per_minute as f64 / 60.0));
+        }
+        buckets
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    refill_per_second: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, refill_per_second: f64, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            capacity,
+            refill_per_second,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// how long until a token is available
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_second)
+    }
+}
+
+/// limits applied to the requests sent to one provider
+pub struct Throttle {
+    host: String,
+    host_limit: RateLimit,
+    key_limit: RateLimit,
+}
+
+impl Throttle {
+    /// `provider` names the settings of the host, e.g. "leads" for `ratelimit.leads.*`
+    pub fn for_provider(host: &str, provider: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            host_limit: RateLimit::from_settings(provider),
+            key_limit: RateLimit::from_settings("key"),
+        }
+    }
+
+    /// blocks until both the host and the API key may send another request,
+    /// returns how long it waited
+    pub fn acquire(&self, api_key: &str) -> Duration {
+        let started = Instant::now();
+        loop {
+            let wait = self.take(&mut BUCKETS.lock().unwrap_or_else(|e| e.into_inner()), api_key, Instant::now());
+            if wait.is_zero() {
+                return started.elapsed();
+            }
+            thread::sleep(wait);
+        }
+    }
+
+    /// takes a token from every bucket of the request at `now`, or from none of them
+    /// and returns how long to wait before asking again
+    fn take(&self, buckets: &mut HashMap<String, TokenBucket>, api_key: &str, now: Instant) -> Duration {
+        let mut wanted = Vec::new();
+        for (window, capacity, refill) in self.host_limit.buckets() {
+            wanted.push((format!("host:{}:{}", self.host, window), capacity, refill));
+        }
+        for (window, capacity, refill) in self.key_limit.buckets() {
+            wanted.push((format!("key:{}:{}", api_key, window), capacity, refill));
+        }
+
+        let mut wait = Duration::ZERO;
+        for (name, capacity, refill) in wanted.iter() {
+            let bucket = buckets
+                .entry(name.clone())
+                .or_insert_with(|| TokenBucket::new(*capacity, *refill, now));
+            //the settings may have changed since the bucket was made
+            bucket.capacity = *capacity;
+            bucket.refill_per_second = *refill;
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_time());
+        }
+        if wait.is_zero() {
+            for (name, ..) in wanted.iter() {
+                if let Some(bucket) = buckets.get_mut(name) {
+                    bucket.tokens -= 1.0;
+                }
+            }
+        }
+        wait
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle(host: (u32, u32), key: (u32, u32)) -> Throttle {
+        Throttle {
+            host: "provider.test".to_string(),
+            host_limit: RateLimit { per_second: host.0, per_minute: host.1 },
+            key_limit: RateLimit { per_second: key.0, per_minute: key.1 },
+        }
+    }
+
+    #[test]
+    fn a_full_bucket_allows_a_burst() {
+        let throttle = throttle((3, 0), (0, 0));
+        let mut buckets = HashMap::new();
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(throttle.take(&mut buckets, "key", start), Duration::ZERO);
+        }
+        let wait = throttle.take(&mut buckets, "key", start);
+        assert!(wait > Duration::from_millis(330) && wait <= Duration::from_millis(334), "{:?}", wait);
+    }
+
+    #[test]
+    fn tokens_refill_up_to_the_capacity() {
+        let throttle = throttle((2, 0), (0, 0));
+        let mut buckets = HashMap::new();
+        let start = Instant::now();
+        throttle.take(&mut buckets, "key", start);
+        throttle.take(&mut buckets, "key", start);
+        //half a second gives back one token
+        let later = start + Duration::from_millis(500);
+        assert_eq!(throttle.take(&mut buckets, "key", later), Duration::ZERO);
+        assert!(!throttle.take(&mut buckets, "key", later).is_zero());
+        //a long pause refills only up to the burst
+        let idle = later + Duration::from_secs(60);
+        for _ in 0..2 {
+            assert_eq!(throttle.take(&mut buckets, "key", idle), Duration::ZERO);
+        }
+        assert!(!throttle.take(&mut buckets, "key", idle).is_zero());
+    }
+
+    #[test]
+    fn a_waiting_bucket_takes_from_none() {
+        //the key allows one request a minute, the host plenty
+        let throttle = throttle((10, 0), (0, 1));
+        let mut buckets = HashMap::new();
+        let start = Instant::now();
+        assert_eq!(throttle.take(&mut buckets, "key", start), Duration::ZERO);
+        let wait = throttle.take(&mut buckets, "key", start);
+        assert_eq!(wait, Duration::from_secs(60));
+        assert_eq!(buckets["host:provider.test:second"].tokens, 9.0);
+        //another key has its own bucket
+        assert_eq!(throttle.take(&mut buckets, "other", start), Duration::ZERO);
+    }
+}
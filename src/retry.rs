@@ -0,0 +1,198 @@
+use std::time::Duration;
+
+use reqwest::{
+    blocking::Response,
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+
+use crate::{config, error::AppError};
+
+/// how failed requests are retried, see the `retry.*` settings
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+    pub max_throttle_wait: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_settings() -> Self {
+        Self {
+            max_retries: config::get_u64("retry.max_retries") as u32,
+            base_delay: Duration::from_millis(config::get_u64("retry.base_delay_ms")),
+            max_delay: Duration::from_millis(config::get_u64("retry.max_delay_ms")),
+            jitter: config::get_bool("retry.jitter"),
+            max_throttle_wait: Duration::from_secs(config::get_u64("retry.max_throttle_wait_secs")),
+        }
+    }
+
+    /// exponential backoff for the given attempt (0 based), with jitter in the upper half
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+}
+
+/// what to do with the result of a request
+/// the failed responses are kept, so the one ending a request can still be recorded
+pub enum Verdict {
+    Success(Response),
+    /// `response` is none for a network error
+    Retry { delay: Duration, reason: String, response: Option<Response> },
+    QuotaExhausted { reason: String, response: Response },
+    /// an answer a retry wouldn't change, like a 4xx
+    Refused(Response),
+    Fail(AppError),
+}
+
+fn header_number(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// a remaining count of 0 in the provider's quota headers means the plan is used up
+fn quota_used_up(headers: &HeaderMap) -> bool {
+    headers.iter().any(|(name, value)| {
+        name.as_str().ends_with("requests-remaining") && value.to_str().is_ok_and(|v| v.trim() == "0")
+    })
+}
+
+/// decides whether a request succeeded, is worth retrying or needs another API key
+pub fn classify(result: reqwest::Result<Response>, policy: &RetryPolicy, attempt: u32) -> Verdict {
+    let response = match result {
+        Err(e) if e.is_timeout() || e.is_connect() || e.is_request() => {
+            return Verdict::Retry {
+                delay: policy.backoff(attempt),
+                reason: format!("network error: {}", e),
+                response: None,
+            };
+        }
+        Err(e) => return Verdict::Fail(e.into()),
+        Ok(response) => response,
+    };
+    let status = response.status();
+    if status.is_success() {
+        return Verdict::Success(response);
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let headers = response.headers();
+        if quota_used_up(headers) {
+            return Verdict::QuotaExhausted {
+                reason: "api key limit reached".to_string(),
+                response,
+            };
+        }
+        return match header_number(headers, RETRY_AFTER.as_str()) {
+            Some(seconds) if Duration::from_secs(seconds) <= policy.max_throttle_wait => Verdict::Retry {
+                delay: Duration::from_secs(seconds),
+                reason: "rate limited by the provider".to_string(),
+                response: Some(response),
+            },
+            Some(seconds) => Verdict::QuotaExhausted {
+                reason: format!("api key limit reached, the provider asks to wait {}s", seconds),
+                response,
+            },
+            //no hint from the provider, assume throttling until the retries run out
+            None if attempt < policy.max_retries => Verdict::Retry {
+                delay: policy.backoff(attempt),
+                reason: "rate limited by the provider".to_string(),
+                response: Some(response),
+            },
+            None => Verdict::QuotaExhausted {
+                reason: "api key limit reached".to_string(),
+                response,
+            },
+        };
+    }
+    if status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT {
+        return Verdict::Retry {
+            delay: policy.backoff(attempt),
+            reason: format!("server error {}", status),
+            response: Some(response),
+        };
+    }
+    Verdict::Refused(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(4),
+            jitter: false,
+            max_throttle_wait: Duration::from_secs(60),
+        }
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> reqwest::Result<Response> {
+        let mut builder = http::Response::builder().status(status);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        Ok(Response::from(builder.body("").unwrap()))
+    }
+
+    fn delay(verdict: Verdict) -> Option<Duration> {
+        match verdict {
+            Verdict::Retry { delay, .. } => Some(delay),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn success_is_returned() {
+        assert!(matches!(classify(response(200, &[]), &policy(), 0), Verdict::Success(_)));
+    }
+
+    #[test]
+    fn retry_after_is_honored() {
+        let verdict = classify(response(429, &[("retry-after", "3")]), &policy(), 0);
+        assert_eq!(delay(verdict), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn retry_after_past_the_throttle_wait_means_quota() {
+        let verdict = classify(response(429, &[("retry-after", "600")]), &policy(), 0);
+        assert!(matches!(verdict, Verdict::QuotaExhausted { .. }));
+    }
+
+    #[test]
+    fn no_requests_remaining_means_quota() {
+        let headers = [("x-ratelimit-requests-remaining", "0"), ("retry-after", "3")];
+        assert!(matches!(classify(response(429, &headers), &policy(), 0), Verdict::QuotaExhausted { .. }));
+        let headers = [("x-ratelimit-requests-remaining", "12")];
+        assert!(matches!(classify(response(429, &headers), &policy(), 0), Verdict::Retry { .. }));
+    }
+
+    #[test]
+    fn throttling_without_a_hint_turns_into_quota() {
+        assert_eq!(delay(classify(response(429, &[]), &policy(), 1)), Some(Duration::from_secs(1)));
+        assert!(matches!(classify(response(429, &[]), &policy(), 2), Verdict::QuotaExhausted { .. }));
+    }
+
+    #[test]
+    fn server_errors_back_off() {
+        assert_eq!(delay(classify(response(503, &[]), &policy(), 0)), Some(Duration::from_millis(500)));
+        assert_eq!(delay(classify(response(408, &[]), &policy(), 1)), Some(Duration::from_secs(1)));
+        //capped at max_delay
+        assert_eq!(delay(classify(response(500, &[]), &policy(), 5)), Some(Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn client_errors_are_refused() {
+        assert!(matches!(classify(response(404, &[]), &policy(), 0), Verdict::Refused(_)));
+        assert!(matches!(classify(response(401, &[]), &policy(), 0), Verdict::Refused(_)));
+    }
+}
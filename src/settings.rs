@@ -1,18 +1,97 @@
 use colorize::AnsiColor;
-use inquire::Select;
+use inquire::{validator::Validation, Select, Text};
+use prettytable::{row, Table};
 
-use crate::{MenuState};
+use crate::{clear_and_logo, config::{self, Setting, Source, SETTINGS}, error::AppResult, http::INSECURE_TLS_WARNING, logging, MenuState};
 
-pub fn main_settings() -> MenuState {
-    let options = vec!["API Keys".blue(), "Lists".blue(), "Back".red()];
-    let selection = Select::new("Settings".green().as_str(), options.clone()).prompt().unwrap();
+pub fn main_settings() -> AppResult<MenuState> {
+    let options = vec!["API Keys".blue(), "Lists".blue(), "General".blue(), "Network".blue(), "Database".blue(), "Back".red()];
+    let selection = Select::new("Settings".green().as_str(), options.clone()).prompt()?;
         match options.iter().position(|x| *x == selection) {
-            Some(0) => MenuState::APIkeys,
-            Some(1) => MenuState::Lists,
-            Some(2) => MenuState::Main,
-            _ => panic!("something went wrong")
+            Some(0) => Ok(MenuState::APIkeys),
+            Some(1) => Ok(MenuState::Lists),
+            Some(2) => Ok(MenuState::GeneralSettings),
+            Some(3) => Ok(MenuState::NetworkSettings),
+            Some(4) => Ok(MenuState::Database),
+            _ => Ok(MenuState::Main),
         }
     }
 
+pub fn general_settings() -> AppResult<MenuState> {
+    edit_section("general", "General Settings", MenuState::GeneralSettings)
+}
 
+pub fn network_settings() -> AppResult<MenuState> {
+    edit_section("network", "Network Settings", MenuState::NetworkSettings)
+}
 
+/// shows every setting of a section and lets the user change one of them
+fn edit_section(section: &str, heading: &str, screen: MenuState) -> AppResult<MenuState> {
+    let settings = SETTINGS
+        .iter()
+        .filter(|setting| setting.section == section)
+        .collect::<Vec<&Setting>>();
+    let mut table = Table::new();
+    table.add_row(row!["setting", "value", "from", "description"]);
+    for setting in settings.iter() {
+        let (_, source) = config::lookup(setting.key);
+        table.add_row(row![setting.key, config::display(setting), source.to_str(), setting.description]);
+    }
+    clear_and_logo(heading.to_string());
+    table.printstd();
+    if let Some(path) = config::config_file() {
+        println!(
+            "{} {}\n",
+            "values can also come from".blue(),
+            format!("{}, APOLLO_<SETTING> variables or --set setting=value", path.display()).blue()
+        );
+    }
+
+    let mut options = settings.iter().map(|setting| setting.key).collect::<Vec<&str>>();
+    options.push("Back");
+    let setting = match Select::new("which setting do you want to change ?", options).prompt()? {
+        "Back" => return Ok(MenuState::Settings),
+        key => config::setting(key)?,
+    };
+
+    //a proxy password stays hidden, leaving the mask keeps it
+    let current = config::display(setting);
+    let value = Text::new(format!("new value for {}:", setting.key).as_str())
+        .with_initial_value(current.as_str())
+        .with_help_message(setting.description)
+        .with_validator(move |v: &str| match config::validate(setting, v) {
+            Ok(_) => Ok(Validation::Valid),
+            Err(e) => Ok(Validation::Invalid(e.into())),
+        })
+        .prompt();
+    //esc goes back to the list of settings
+    if let Ok(value) = value {
+        match config::set(setting.key, &config::unmask(setting, &value)) {
+            Ok(_) => {
+                log::info!("setting changed key={} value={}", setting.key, config::display(setting));
+                println!("{} \n", "saved successfully".blue());
+                let (_, source) = config::lookup(setting.key);
+                if source > Source::Database {
+                    println!(
+                        "{} \n",
+                        format!(
+                            "{} is set from the {} ({} / --set), the saved value applies once that is removed",
+                            setting.key,
+                            source.to_str(),
+                            config::env_var(setting.key)
+                        )
+                        .red()
+                    );
+                }
+                if setting.key.starts_with("log.") {
+                    logging::apply_settings();
+                }
+                if setting.key == "tls.accept_invalid_certs" && config::get_bool(setting.key) {
+                    println!("{} \n", INSECURE_TLS_WARNING.red());
+                }
+            }
+            Err(e) => println!("{} error: {:?} \n", "couldn't save the setting".red(), e),
+        }
+    }
+    Ok(screen)
+}
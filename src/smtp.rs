@@ -0,0 +1,494 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    config,
+    dns::{self, UdpResolver},
+    emails::Verifier,
+    error::{AppError, AppResult},
+    jobs::{lock, Reporter},
+    progress::Progress,
+};
+
+/// what a mail server said about a recipient
+enum Answer {
+    Accepted,
+    Rejected(String),
+    /// a 4xx, greylisting or a full mailbox, worth asking again later
+    Deferred(String),
+}
+
+/// verifies addresses by asking the mail server of their domain, with EHLO, MAIL FROM and RCPT TO,
+/// the conversation ends with QUIT before any message is sent
+pub struct SmtpVerifier {
+    /// looks up the MX records, none when `smtp.server` is set
+    resolver: Option<UdpResolver>,
+    server: Option<String>,
+    port: u16,
+    helo_name: String,
+    mail_from: String,
+    timeout: Duration,
+    greylist_retries: u64,
+    greylist_wait: Duration,
+    /// port 25 is often blocked, so the job stops when this many domains in a row can't be reached
+    max_unreachable: u64,
+    unreachable: AtomicU64,
+    /// domains no server answered for, their other addresses are skipped without connecting again
+    unreachable_domains: Mutex<HashSet<String>>,
+    /// whether a domain accepts any address, probed once per domain and job
+    catch_all: Mutex<HashMap<String, bool>>,
+    progress: Mutex<Progress>,
+    reporter: Reporter,
+}
+
+impl SmtpVerifier {
+    pub fn from_settings(progress: Progress, reporter: &Reporter) -> AppResult<Self> {
+        let server = match config::get("smtp.server").trim() {
+            "" => None,
+            server => Some(server.to_string()),
+        };
+        let resolver = match server {
+            Some(_) => None,
+            None => Some(UdpResolver::from_settings()?),
+        };
+        Ok(Self {
+            resolver,
+            server,
+            port: config::get_u64("smtp.port").clamp(1, u16::MAX as u64) as u16,
+            helo_name: config::get("smtp.helo_name").trim().to_string(),
+            mail_from: config::get("smtp.mail_from").trim().to_string(),
+            timeout: Duration::from_secs(config::get_u64("smtp.timeout_secs").max(1)),
+            greylist_retries: config::get_u64("smtp.greylist_retries"),
+            greylist_wait: Duration::from_secs(config::get_u64("smtp.greylist_wait_secs")),
+            max_unreachable: config::get_u64("smtp.max_unreachable").max(1),
+            unreachable: AtomicU64::new(0),
+            unreachable_domains: Mutex::new(HashSet::new()),
+            catch_all: Mutex::new(HashMap::new()),
+            progress: Mutex::new(progress),
+            reporter: reporter.clone(),
+        })
+    }
+
+    /// `smtp.server` when set, the MX records of the domain otherwise
+    fn servers(&self, domain: &str) -> AppResult<Vec<String>> {
+        match (&self.server, &self.resolver) {
+            (Some(server), _) if server.contains(':') => Ok(vec![server.clone()]),
+            (Some(server), _) => Ok(vec![format!("{}:{}", server, self.port)]),
+            (None, Some(resolver)) => Ok(dns::exchangers(resolver, domain)?
+                .into_iter()
+                .map(|exchange| format!("{}:{}", exchange, self.port))
+                .collect()),
+            (None, None) => Err(AppError::Other("no mail server to ask".to_string())),
+        }
+    }
+
+    fn connect(&self, server: &str) -> AppResult<TcpStream> {
+        let mut last_error = AppError::Network(format!("{} has no address", server));
+        for address in server.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => last_error = AppError::Network(format!("{}: {}", server, e)),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// one conversation with a mail server, `probe` is also asked for when the address is accepted
+    /// returns the answer for the address and whether the probe was accepted too
+    fn converse(&self, server: &str, email: &str, probe: Option<&str>) -> AppResult<(Answer, Option<bool>)> {
+        let stream = self.connect(server)?;
+        let mut session = Session {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            server,
+        };
+        let answer = self.recipient(&mut session, email, probe);
+        //the answer is already known, a server closing the connection early doesn't change it
+        _ = session.command("QUIT");
+        answer
+    }
+
+    fn recipient(&self, session: &mut Session, email: &str, probe: Option<&str>) -> AppResult<(Answer, Option<bool>)> {
+        if let Some(deferred) = session.expect(None, 220)? {
+            return Ok((deferred, None));
+        }
+        let (code, text) = session.command(&format!("EHLO {}", self.helo_name))?;
+        if (400..=499).contains(&code) {
+            return Ok((Answer::Deferred(format!("{} {}", code, text)), None));
+        }
+        if code != 250
+            && let Some(deferred) = session.expect(Some(&format!("HELO {}", self.helo_name)), 250)?
+        {
+            return Ok((deferred, None));
+        }
+        if let Some(deferred) = session.expect(Some(&format!("MAIL FROM:<{}>", self.mail_from)), 250)? {
+            return Ok((deferred, None));
+        }
+
+        let (code, text) = session.command(&format!("RCPT TO:<{}>", email))?;
+        let answer = match code {
+            250 | 251 => Answer::Accepted,
+            400..=499 => Answer::Deferred(format!("{} {}", code, text)),
+            _ => Answer::Rejected(format!("{} {}", code, text)),
+        };
+        let mut probed = None;
+        if let (Answer::Accepted, Some(probe)) = (&answer, probe) {
+            //the address was accepted all the same, the domain is probed again with the next one
+            probed = match session.command(&format!("RCPT TO:<{}>", probe)) {
+                Ok((code, _)) => Some(matches!(code, 250 | 251)),
+                Err(e) => {
+                    log::warn!("smtp probe server={} email={} error=\"{}\"", session.server, probe, e);
+                    None
+                }
+            };
+        }
+        Ok((answer, probed))
+    }
+
+    /// asks the servers of the domain in order until one answers, waiting out greylisting
+    fn ask(&self, email: &str, domain: &str) -> AppResult<Option<String>> {
+        if lock(&self.unreachable_domains).contains(domain) {
+            log::debug!("smtp skipped unreachable domain={} email={}", domain, email);
+            return Ok(None);
+        }
+        let known = lock(&self.catch_all).get(domain).copied();
+        let probe = match known {
+            None => Some(format!("{}@{}", (0..16).map(|_| fastrand::lowercase()).collect::<String>(), domain)),
+            Some(_) => None,
+        };
+        let servers = self.servers(domain)?;
+        if servers.is_empty() {
+            return Ok(None);
+        }
+
+        let mut attempt = 0;
+        loop {
+            let mut answered = None;
+            let mut last_error = None;
+            for server in servers.iter() {
+                {
+                    let mut progress = self.progress();
+                    progress.request(server.clone());
+                    self.reporter.progress(&progress);
+                }
+                match self.converse(server, email, probe.as_deref()) {
+                    Ok(answer) => {
+                        answered = Some(answer);
+                        break;
+                    }
+                    Err(e) => {
+                        log::warn!("smtp server={} email={} error=\"{}\"", server, email, e);
+                        last_error = Some(e);
+                    }
+                }
+            }
+            let Some((answer, probed)) = answered else {
+                let last_error = last_error.unwrap_or_else(|| AppError::Network(format!("no mail server of {} answered", domain)));
+                lock(&self.unreachable_domains).insert(domain.to_string());
+                let unreachable = self.unreachable.fetch_add(1, Ordering::Relaxed) + 1;
+                if unreachable >= self.max_unreachable {
+                    return Err(AppError::Network(format!(
+                        "{} domains in a row had no reachable mail server, is port 25 blocked? {}",
+                        unreachable, last_error
+                    )));
+                }
+                log::warn!("smtp unreachable domain={} email={} in_a_row={}", domain, email, unreachable);
+                return Ok(None);
+            };
+            self.unreachable.store(0, Ordering::Relaxed);
+            if let Some(catch_all) = probed {
+                lock(&self.catch_all).insert(domain.to_string(), catch_all);
+            }
+            match answer {
+                Answer::Accepted => {
+                    log::info!("smtp verified email={} catch_all={:?}", email, known.or(probed));
+                    return Ok(Some(match known.or(probed) {
+                        Some(true) => "accept_all".to_string(),
+                        _ => "valid".to_string(),
+                    }));
+                }
+                Answer::Rejected(reply) => {
+                    log::info!("smtp rejected email={} reply=\"{}\"", email, reply);
+                    return Ok(None);
+                }
+                Answer::Deferred(reply) if attempt < self.greylist_retries => {
+                    attempt += 1;
+                    self.reporter.info(format!(
+                        "{} deferred ({}), asking again in {}s",
+                        email,
+                        reply,
+                        self.greylist_wait.as_secs()
+                    ));
+                    thread::sleep(self.greylist_wait);
+                }
+                Answer::Deferred(reply) => {
+                    self.reporter.info(format!("couldn't verify {}: still deferred ({})", email, reply));
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+impl Verifier for SmtpVerifier {
+    fn check(&self, email: &str) -> AppResult<Option<String>> {
+        let Some((_, domain)) = email.rsplit_once('@').filter(|_| !email.contains(['\r', '\n', '<', '>'])) else {
+            return Err(AppError::Parse(format!("the address {}", email)));
+        };
+        let status = self.ask(email, domain)?;
+        self.progress().tried += 1;
+        Ok(status)
+    }
+
+    fn progress(&self) -> MutexGuard<'_, Progress> {
+        lock(&self.progress)
+    }
+
+    fn reporter(&self) -> &Reporter {
+        &self.reporter
+    }
+}
+
+/// the command and reply lines of one connection
+struct Session<'a> {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    server: &'a str,
+}
+
+impl Session<'_> {
+    /// sends a command, or only reads the greeting when there is none, and returns the reply
+    fn command(&mut self, command: &str) -> AppResult<(u16, String)> {
+        //a line break would end the command early and send the rest as another one
+        if command.contains(['\r', '\n']) {
+            return Err(AppError::Parse(format!("the command {:?}, it contains a line break", command)));
+        }
+        log::debug!("smtp server={} > {}", self.server, command);
+        self.writer.write_all(format!("{}\r\n", command).as_bytes())?;
+        self.reply()
+    }
+
+    /// a 4xx reply is returned as deferred, any other unexpected one is an error
+    fn expect(&mut self, command: Option<&str>, code: u16) -> AppResult<Option<Answer>> {
+        let (answered, text) = match command {
+            Some(command) => self.command(command)?,
+            None => self.reply()?,
+        };
+        if (400..=499).contains(&answered) {
+            return Ok(Some(Answer::Deferred(format!("{} {}", answered, text))));
+        }
+        if answered != code {
+            return Err(AppError::Provider(format!(
+                "mail server {} answered {} {} to {}",
+                self.server,
+                answered,
+                text,
+                command.unwrap_or("the connection")
+            )));
+        }
+        Ok(None)
+    }
+
+    /// reads a reply, multiline ones continue with `250-` until the `250 ` line
+    fn reply(&mut self) -> AppResult<(u16, String)> {
+        let mut text = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(AppError::Network(format!("mail server {} closed the connection", self.server)));
+            }
+            let line = line.trim_end();
+            log::debug!("smtp server={} < {}", self.server, line);
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| AppError::Parse(format!("the reply of mail server {}: {}", self.server, line)))?;
+            text.push(line.get(4..).unwrap_or_default().to_string());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text.join(" ")));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::mpsc};
+
+    use super::*;
+    use crate::progress::ProgressKind;
+
+    /// a mail server answering RCPT TO with the scripted codes, one list of codes per connection
+    fn scripted_server(connections: Vec<Vec<u16>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for codes in connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                let mut codes = codes.into_iter();
+                writer.write_all(b"220 fake ready\r\n").unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                    let reply = match line.get(..4).unwrap_or_default() {
+                        "EHLO" => "250-fake\r\n250 SIZE 1000\r\n".to_string(),
+                        "MAIL" => "250 ok\r\n".to_string(),
+                        "RCPT" => format!("{} scripted\r\n", codes.next().unwrap_or(550)),
+                        "QUIT" => "221 bye\r\n".to_string(),
+                        _ => "500 unknown\r\n".to_string(),
+                    };
+                    if writer.write_all(reply.as_bytes()).is_err() || line.starts_with("QUIT") {
+                        break;
+                    }
+                    line.clear();
+                }
+            }
+        });
+        address
+    }
+
+    /// a mail server sending the replies in order, the greeting first, and closing the connection after the last
+    fn replying_server(connections: Vec<Vec<&'static str>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for replies in connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                let mut line = String::new();
+                for (i, reply) in replies.into_iter().enumerate() {
+                    if i > 0 && reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    _ = writer.write_all(format!("{}\r\n", reply).as_bytes());
+                    line.clear();
+                }
+            }
+        });
+        address
+    }
+
+    fn verifier(server: String, greylist_retries: u64) -> SmtpVerifier {
+        SmtpVerifier {
+            resolver: None,
+            server: Some(server),
+            port: 25,
+            helo_name: "localhost".to_string(),
+            mail_from: String::new(),
+            timeout: Duration::from_secs(2),
+            greylist_retries,
+            greylist_wait: Duration::ZERO,
+            max_unreachable: 2,
+            unreachable: AtomicU64::new(0),
+            unreachable_domains: Mutex::new(HashSet::new()),
+            catch_all: Mutex::new(HashMap::new()),
+            progress: Mutex::new(Progress::new(ProgressKind::Emails, 1)),
+            //nothing reads the events, the console reporter would print them between the test results
+            reporter: Reporter::Channel { job: 0, sender: mpsc::channel().0 },
+        }
+    }
+
+    #[test]
+    fn accepted_address_with_rejected_probe_is_valid() {
+        let verifier = verifier(scripted_server(vec![vec![250, 550], vec![250]]), 0);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap().as_deref(), Some("valid"));
+        assert_eq!(lock(&verifier.catch_all).get("example.com"), Some(&false));
+        //the domain is known now, so the second conversation has no probe
+        assert_eq!(verifier.ask("john@example.com", "example.com").unwrap().as_deref(), Some("valid"));
+    }
+
+    #[test]
+    fn accepted_probe_means_accept_all() {
+        let verifier = verifier(scripted_server(vec![vec![250, 250]]), 0);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap().as_deref(), Some("accept_all"));
+    }
+
+    #[test]
+    fn rejected_address_is_none() {
+        let verifier = verifier(scripted_server(vec![vec![550]]), 0);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap(), None);
+    }
+
+    #[test]
+    fn deferred_address_is_asked_again() {
+        let verifier = verifier(scripted_server(vec![vec![450], vec![250, 550]]), 1);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap().as_deref(), Some("valid"));
+    }
+
+    #[test]
+    fn still_deferred_address_is_none() {
+        let verifier = verifier(scripted_server(vec![vec![450], vec![451]]), 1);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap(), None);
+    }
+
+    #[test]
+    fn busy_server_is_deferred_not_unreachable() {
+        let server = replying_server(vec![
+            vec!["421 too busy"],
+            vec!["220 hi", "250 hi", "451 try later"],
+            vec!["220 hi", "250 hi", "250 ok", "250 ok", "550 no"],
+        ]);
+        let verifier = verifier(server, 2);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap().as_deref(), Some("valid"));
+        assert!(lock(&verifier.unreachable_domains).is_empty());
+    }
+
+    #[test]
+    fn failed_probe_keeps_the_answer() {
+        //the connection closes after the address is accepted
+        let verifier = verifier(replying_server(vec![vec!["220 hi", "250 hi", "250 ok", "250 ok"]]), 0);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap().as_deref(), Some("valid"));
+        assert_eq!(lock(&verifier.catch_all).get("example.com"), None);
+    }
+
+    #[test]
+    fn line_breaks_are_never_sent() {
+        let verifier = verifier(scripted_server(vec![vec![250, 550]]), 0);
+        assert!(verifier.check("jane@example.com>\r\nRCPT TO:<john@example.com").is_err());
+        assert_eq!(verifier.unreachable.load(Ordering::Relaxed), 0);
+
+        let stream = verifier.connect(verifier.server.as_deref().unwrap()).unwrap();
+        let mut session = Session {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+            server: "fake",
+        };
+        assert!(session.command("MAIL FROM:<>\r\nRSET").is_err());
+    }
+
+    #[test]
+    fn unreachable_domains_stop_the_job_only_in_a_row() {
+        //a port nothing listens on anymore
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let verifier = verifier(closed, 0);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap(), None);
+        //the domain isn't asked again, so it doesn't count twice
+        assert_eq!(verifier.ask("john@example.com", "example.com").unwrap(), None);
+        assert!(verifier.ask("jane@example.org", "example.org").is_err());
+    }
+
+    #[test]
+    fn an_answer_resets_the_unreachable_count() {
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let mut verifier = verifier(closed, 0);
+        assert_eq!(verifier.ask("jane@example.com", "example.com").unwrap(), None);
+        verifier.server = Some(scripted_server(vec![vec![550]]));
+        assert_eq!(verifier.ask("jane@example.org", "example.org").unwrap(), None);
+        assert_eq!(verifier.unreachable.load(Ordering::Relaxed), 0);
+    }
+}
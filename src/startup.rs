@@ -1,24 +1,115 @@
-use std::{fs, path::{PathBuf}};
+use std::{fs, path::{Path, PathBuf}};
 
-use crate::db::open_connection;
+use rusqlite::Connection;
 
-pub fn sqlite_init(data_path: &PathBuf) {
+use crate::{backup, classify, domain, emails, error::AppResult, organizations};
+
+type Migration = fn(&Connection) -> Result<(), rusqlite::Error>;
+
+/// schema changes made after the first release, applied in order
+/// `PRAGMA user_version` counts how many of them a database already has
+const MIGRATIONS: &[(&str, Migration)] = &[
+    ("organizations", organizations::migrate),
+    ("domains", domain::migrate),
+    ("email confidence", emails::migrate),
+    ("email kinds", classify::migrate),
+];
+
+pub fn sqlite_init(data_path: &PathBuf) -> AppResult<()> {
     //open the file
-   _= fs::File::create_new(data_path);
+   let created = fs::File::create_new(data_path).is_ok();
 
-   //initiate all the tables
-   let connection = open_connection();
+   //initiate all the tables, on the file given as the profile isn't active until this succeeded
+   let mut connection = Connection::open(data_path)?;
+   create_tables(&connection)?;
+   //nothing to lose in a new database
+   migrate(&mut connection, (!created).then_some(data_path))
+}
+
+/// the tables of the first release, the migrations change them from there
+fn create_tables(connection: &Connection) -> Result<(), rusqlite::Error> {
 let queries = [
     "CREATE TABLE IF NOT EXISTS apiKeys (key TEXT PRIMARY KEY, for TEXT);",
     "CREATE TABLE IF NOT EXISTS lists (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, leadsFetched INTEGER, emailsFetched INTEGER, filter INTEGER, next TEXT);",
     "CREATE TABLE IF NOT EXISTS filters (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, person TEXT, location TEXT, industry TEXT, keywords TEXT, employeeSize TEXT);",
     "CREATE TABLE IF NOT EXISTS leads (id TEXT, first_name TEXT, last_name TEXT, name TEXT, title TEXT, linkedin_url TEXT, state TEXT, city TEXT, org_website TEXT, country TEXT, org_name TEXT, org_fb_url TEXT, org_linkedin_url TEXT, email TEXT, listId INTEGER);",
+    "CREATE TABLE IF NOT EXISTS settings (key TEXT PRIMARY KEY, value TEXT);",
 ];
 
 
 for query in queries.iter() {
-    connection.execute(query, []).unwrap();
+    connection.execute(query, [])?;
 }
+Ok(())
 }
 
+/// applies the migrations the database doesn't have yet, each one in its own transaction
+/// the database at `backup_of` is backed up first
+fn migrate(connection: &mut Connection, backup_of: Option<&Path>) -> AppResult<()> {
+    let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= MIGRATIONS.len() {
+        return Ok(());
+    }
+    if let Some(data_path) = backup_of {
+        backup::create_of(data_path, "migration")?;
+    }
+    for (index, (name, migration)) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        migration(&transaction)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+        log::info!("migration applied name={} version={}", name, index + 1);
+    }
+    Ok(())
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(connection: &Connection, table: &str) -> Vec<String> {
+        connection
+            .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<Vec<String>, rusqlite::Error>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn migrations_bring_a_first_release_database_up_to_date() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_tables(&connection).unwrap();
+        connection
+            .execute_batch(
+                "INSERT INTO leads (id, name, org_website, org_name, email, listId)
+                 VALUES ('1', 'Jane Doe', 'https://www.Acme.co.uk/about', 'Acme', 'sales@acme.co.uk', 1);",
+            )
+            .unwrap();
+
+        migrate(&mut connection, None).unwrap();
+        let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        let leads = columns(&connection, "leads");
+        for column in ["orgId", "domain", "emailConfidence", "emailKind"] {
+            assert!(leads.contains(&column.to_string()), "leads has no {} column", column);
+        }
+        assert!(columns(&connection, "organizations").contains(&"catchAll".to_string()));
+
+        let (domain, kind, org_domain): (String, String, String) = connection
+            .query_row(
+                "SELECT leads.domain, leads.emailKind, organizations.domain FROM leads
+                 INNER JOIN organizations ON organizations.id = leads.orgId",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(domain, "acme.co.uk");
+        assert_eq!(kind, "role");
+        assert_eq!(org_domain, "acme.co.uk");
+
+        //an up to date database is left alone
+        migrate(&mut connection, None).unwrap();
+    }
+}
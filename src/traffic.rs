@@ -0,0 +1,91 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    error::{AppError, AppResult},
+    logging::redact,
+};
+
+/// whether provider responses are recorded to or replayed from disk, see `--record` and `--replay`
+/// only the HTTP providers are recorded, a replay skips the MX pre-check and refuses the smtp backend
+pub enum Traffic {
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+static TRAFFIC: OnceLock<Traffic> = OnceLock::new();
+
+/// `--record` and `--replay` use `<data dir>/traffic` unless given a directory, e.g. `--replay=fixtures`
+pub fn from_args(args: &[String], data_dir: &Path) -> Traffic {
+    let dir = |flag: &str| {
+        args.iter().find_map(|arg| match arg.strip_prefix(flag)? {
+            "" => Some(data_dir.join("traffic")),
+            dir => dir.strip_prefix('=').map(PathBuf::from),
+        })
+    };
+    if let Some(dir) = dir("--replay") {
+        return Traffic::Replay(dir);
+    }
+    if let Some(dir) = dir("--record") {
+        return Traffic::Record(dir);
+    }
+    Traffic::Live
+}
+
+pub fn set(traffic: Traffic) {
+    _ = TRAFFIC.set(traffic);
+}
+
+pub fn mode() -> &'static Traffic {
+    TRAFFIC.get().unwrap_or(&Traffic::Live)
+}
+
+/// a provider response as stored on disk, the body is kept verbatim so responses
+/// that failed to parse can be replayed as they were
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    pub url: String,
+    pub status: u16,
+    pub recorded_at: String,
+    pub body: String,
+}
+
+/// one file per url, named after the host and a hash of the whole url
+fn file_name(url: &Url) -> String {
+    //FNV-1a, stable across runs and rust versions unlike the std hasher
+    let hash = url
+        .as_str()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("{}-{:016x}.json", url.host_str().unwrap_or("unknown"), hash)
+}
+
+pub fn save(dir: &Path, url: &Url, status: u16, body: &str) -> AppResult<PathBuf> {
+    let recording = Recording {
+        url: redact(url.as_str()),
+        status,
+        recorded_at: Local::now().to_rfc3339(),
+        body: redact(body),
+    };
+    fs::create_dir_all(dir)?;
+    let path = dir.join(file_name(url));
+    let json = serde_json::to_string_pretty(&recording)
+        .map_err(|e| AppError::Other(format!("couldn't encode the recording: {}", e)))?;
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+pub fn load(dir: &Path, url: &Url) -> AppResult<Recording> {
+    let path = dir.join(file_name(url));
+    let json = fs::read_to_string(&path)
+        .map_err(|_| AppError::Other(format!("no recorded response for {} in {}", url, dir.display())))?;
+    serde_json::from_str(&json).map_err(|e| AppError::Parse(format!("the recording {}: {}", path.display(), e)))
+}
@@ -0,0 +1,809 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Cell, Clear, Gauge, List as ListWidget, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    apikey::{add_new_api_key, ApiKey, ApiKeyFor},
+    db::{api_keys_for, list_all, DBentity, Tablular},
+    error::AppError,
+    emails::{self, run_emails_job},
+    handle_state,
+    jobs::{self, JobEvent, ListLock, Reporter},
+    leads::{confidence_label, kind_label, run_leads_job, Lead, LeadField},
+    profile,
+    progress::Progress,
+    list::{add_new_list, List},
+    viewleads::lead_detail,
+    MenuState,
+};
+
+const MAX_COLUMN_WIDTH: usize = 40;
+const JOB_LOG_LINES: usize = 200;
+
+/// an entry of the sidebar, each one opens a screen
+enum SidebarItem {
+    Overview,
+    Lists,
+    List(usize),
+    ApiKeys,
+    Jobs,
+    Settings,
+}
+
+impl SidebarItem {
+    fn screen(&self) -> MenuState {
+        match self {
+            Self::Overview => MenuState::Main,
+            Self::Lists => MenuState::Lists,
+            Self::List(_) => MenuState::ViewLeads,
+            Self::ApiKeys => MenuState::APIkeys,
+            Self::Jobs => MenuState::Fetch,
+            Self::Settings => MenuState::Settings,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FetchKind {
+    Leads,
+    Emails,
+}
+
+/// a fetch running in the background
+struct Job {
+    title: String,
+    log: Vec<JobEvent>,
+    progress: Option<Progress>,
+    finished: bool,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// prompt shown on top of the screens when starting a fetch
+struct CountPopup {
+    kind: FetchKind,
+    list: List,
+    input: String,
+    error: Option<String>,
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Sidebar,
+    Main,
+}
+
+struct App {
+    first_time: bool,
+    screen: MenuState,
+    focus: Focus,
+    sidebar: Vec<SidebarItem>,
+    sidebar_state: ListState,
+    lists: Vec<List>,
+    leads: Vec<Lead>,
+    api_keys: Vec<ApiKey>,
+    table_state: TableState,
+    column_offset: usize,
+    jobs: Vec<Job>,
+    sender: Sender<(usize, JobEvent)>,
+    receiver: Receiver<(usize, JobEvent)>,
+    popup: Option<CountPopup>,
+    status: Option<(bool, String)>,
+    /// q was pressed once while jobs were running
+    quit_pending: bool,
+    quit: bool,
+}
+
+/// runs the full-screen UI until the user quits
+pub fn run(first_time: bool) {
+    let (sender, receiver) = mpsc::channel();
+    let mut app = App {
+        first_time,
+        screen: MenuState::Main,
+        focus: Focus::Sidebar,
+        sidebar: Vec::new(),
+        sidebar_state: ListState::default().with_selected(Some(0)),
+        lists: Vec::new(),
+        leads: Vec::new(),
+        api_keys: Vec::new(),
+        table_state: TableState::default(),
+        column_offset: 0,
+        jobs: Vec::new(),
+        sender,
+        receiver,
+        popup: None,
+        status: None,
+        quit_pending: false,
+        quit: false,
+    };
+    app.reload();
+
+    let mut terminal = ratatui::init();
+    while !app.quit {
+        if let Err(e) = terminal.draw(|frame| app.draw(frame)) {
+            app.set_error(format!("couldn't draw the screen: {}", e));
+        }
+        app.collect_job_events();
+        match event::poll(Duration::from_millis(100)) {
+            Ok(true) => {
+                if let Ok(Event::Key(key)) = event::read()
+                    && key.kind == KeyEventKind::Press
+                {
+                    terminal = app.on_key(key, terminal);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => app.set_error(format!("couldn't read input: {}", e)),
+        }
+    }
+    ratatui::restore();
+}
+
+impl App {
+    fn set_error(&mut self, message: String) {
+        self.status = Some((true, message));
+    }
+
+    /// cancelling a prompt is not worth a message
+    fn set_app_error(&mut self, error: AppError) {
+        if !matches!(error, AppError::Cancelled) {
+            self.set_error(error.to_string());
+        }
+    }
+
+    fn set_info(&mut self, message: String) {
+        self.status = Some((false, message));
+    }
+
+    fn selected_item(&self) -> Option<&SidebarItem> {
+        self.sidebar_state.selected().and_then(|i| self.sidebar.get(i))
+    }
+
+    fn selected_list(&self) -> Option<List> {
+        match self.selected_item() {
+            Some(SidebarItem::List(index)) => self.lists.get(*index).cloned(),
+            _ => None,
+        }
+    }
+
+    /// reloads everything shown from the database, keeping the selection when possible
+    fn reload(&mut self) {
+        let selected_list_id = self.selected_list().map(|list| list.id);
+        let selected_screen = self.selected_item().map(|item| item.screen());
+
+        self.lists = match list_all::<List>(List::custom_query().map(|v| v.to_string()), None) {
+            Ok(lists) => lists.items,
+            Err(e) => {
+                self.set_error(format!("couldn't fetch lists from DB: {}", e));
+                Vec::new()
+            }
+        };
+        self.api_keys = match list_all::<ApiKey>(None, None) {
+            Ok(keys) => keys.items,
+            Err(e) => {
+                self.set_error(format!("couldn't fetch API keys from DB: {}", e));
+                Vec::new()
+            }
+        };
+
+        self.sidebar = vec![SidebarItem::Overview, SidebarItem::Lists];
+        self.sidebar
+            .extend((0..self.lists.len()).map(SidebarItem::List));
+        self.sidebar.push(SidebarItem::ApiKeys);
+        self.sidebar.push(SidebarItem::Jobs);
+        self.sidebar.push(SidebarItem::Settings);
+
+        let position = self.sidebar.iter().position(|item| match item {
+            SidebarItem::List(index) => Some(self.lists[*index].id) == selected_list_id,
+            item => selected_list_id.is_none() && Some(item.screen()) == selected_screen,
+        });
+        self.sidebar_state.select(Some(position.unwrap_or(0)));
+        self.open_selected();
+    }
+
+    /// shows the screen of the selected sidebar entry
+    fn open_selected(&mut self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+        let screen = item.screen();
+        let list = self.selected_list();
+        if screen == MenuState::Settings {
+            return;
+        }
+        self.screen = screen;
+        self.leads = match list {
+            Some(list) => {
+                let query = format!("SELECT * FROM {} WHERE listId = {}", Lead::table_name(), list.id);
+                match list_all::<Lead>(Some(query), None) {
+                    Ok(leads) => leads.items,
+                    Err(e) => {
+                        self.set_error(format!("couldn't fetch leads from DB: {}", e));
+                        Vec::new()
+                    }
+                }
+            }
+            None => Vec::new(),
+        };
+        let rows = self.rows().1.len();
+        if self.table_state.selected().is_none_or(|i| i >= rows) {
+            self.table_state.select(if rows == 0 { None } else { Some(0) });
+        }
+    }
+
+    /// headers and rows of the table on the current screen
+    fn rows(&self) -> (Vec<String>, Vec<Vec<String>>) {
+        match self.screen {
+            MenuState::ViewLeads => (
+                LeadField::ALL
+                    .iter()
+                    .map(|f| f.to_str().to_string())
+                    .chain(["email confidence".to_string(), "email kind".to_string()])
+                    .collect(),
+                self.leads
+                    .iter()
+                    .map(|lead| {
+                        LeadField::ALL
+                            .iter()
+                            .map(|f| lead.get(*f).unwrap_or("not available".to_string()))
+                            .chain([
+                                match lead.email {
+                                    Some(_) => confidence_label(lead.email_confidence),
+                                    None => String::new(),
+                                },
+                                kind_label(lead.email_kind),
+                            ])
+                            .collect()
+                    })
+                    .collect(),
+            ),
+            MenuState::Lists => tabular(&self.lists),
+            MenuState::APIkeys => tabular(&self.api_keys),
+            _ => (Vec::new(), Vec::new()),
+        }
+    }
+
+    fn collect_job_events(&mut self) {
+        let mut finished_any = false;
+        while let Ok((job, event)) = self.receiver.try_recv() {
+            if let Some(job) = self.jobs.get_mut(job) {
+                if let JobEvent::Progress(progress) = event {
+                    job.progress = Some(progress);
+                    continue;
+                }
+                if matches!(event, JobEvent::Finished) {
+                    job.finished = true;
+                    finished_any = true;
+                }
+                job.log.push(event);
+                if job.log.len() > JOB_LOG_LINES {
+                    job.log.remove(0);
+                }
+            }
+        }
+        //a job that panicked never reports back
+        for job in self.jobs.iter_mut() {
+            if !job.finished && job.handle.as_ref().is_some_and(|h| h.is_finished()) {
+                job.finished = true;
+                job.log.push(JobEvent::Error("the job stopped unexpectedly".to_string()));
+                finished_any = true;
+            }
+        }
+        if finished_any {
+            self.reload();
+        }
+    }
+
+    fn running_jobs(&self) -> usize {
+        self.jobs.iter().filter(|job| !job.finished).count()
+    }
+
+    /// quitting stops the running jobs mid-write, so it is asked twice while there are any
+    fn request_quit(&mut self) {
+        let running = jobs::running();
+        if running == 0 || self.quit_pending {
+            self.quit = true;
+            return;
+        }
+        self.quit_pending = true;
+        self.set_error(format!(
+            "{} job(s) still running, press q again to stop them and quit",
+            running
+        ));
+    }
+
+    /// leaves the full-screen UI to run the prompt based screens from `state`
+    /// until they return to a screen the UI can show itself
+    fn run_classic(&mut self, mut state: MenuState, terminal: DefaultTerminal) -> DefaultTerminal {
+        ratatui::restore();
+        loop {
+            state = handle_state(state, self.first_time);
+            if matches!(
+                state,
+                MenuState::Main | MenuState::Lists | MenuState::ViewLeads | MenuState::APIkeys | MenuState::Fetch
+            ) {
+                break;
+            }
+            if state == MenuState::GoodBye {
+                self.request_quit();
+                break;
+            }
+        }
+        drop(terminal);
+        let terminal = ratatui::init();
+        self.reload();
+        self.show_screen(state);
+        terminal
+    }
+
+    /// suspends the UI for a single prompt based flow
+    fn suspended<F: FnOnce(&mut Self)>(&mut self, terminal: DefaultTerminal, flow: F) -> DefaultTerminal {
+        ratatui::restore();
+        flow(self);
+        drop(terminal);
+        let terminal = ratatui::init();
+        self.reload();
+        terminal
+    }
+
+    fn show_screen(&mut self, screen: MenuState) {
+        let position = self.sidebar.iter().position(|item| item.screen() == screen);
+        if let Some(position) = position {
+            self.sidebar_state.select(Some(position));
+            self.open_selected();
+        }
+    }
+
+    fn on_key(&mut self, key: KeyEvent, terminal: DefaultTerminal) -> DefaultTerminal {
+        if self.popup.is_some() {
+            self.on_popup_key(key);
+            return terminal;
+        }
+        if !matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+            self.quit_pending = false;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.request_quit(),
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Sidebar => Focus::Main,
+                    Focus::Main => Focus::Sidebar,
+                }
+            }
+            KeyCode::Char('r') => {
+                self.reload();
+                self.set_info("reloaded".to_string());
+            }
+            KeyCode::Char('m') => return self.run_classic(MenuState::Main, terminal),
+            KeyCode::Char('s') => return self.run_classic(MenuState::Settings, terminal),
+            KeyCode::Char('n') => {
+                let lists = self.lists.clone();
+                return self.suspended(terminal, |app| {
+                    if let Err(e) = add_new_list(lists) {
+                        app.set_app_error(e);
+                    }
+                });
+            }
+            KeyCode::Char('a') => {
+                let keys = self.api_keys.clone();
+                return self.suspended(terminal, |app| {
+                    if let Err(e) = add_new_api_key(keys) {
+                        app.set_app_error(e);
+                    }
+                });
+            }
+            KeyCode::Char('f') => self.open_popup(FetchKind::Leads),
+            KeyCode::Char('e') => self.open_popup(FetchKind::Emails),
+            KeyCode::Char('j') | KeyCode::Down => self.move_selection(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(20),
+            KeyCode::PageUp => self.move_selection(-20),
+            KeyCode::Char('h') | KeyCode::Left => self.column_offset = self.column_offset.saturating_sub(1),
+            KeyCode::Char('l') | KeyCode::Right => {
+                let columns = self.rows().0.len();
+                self.column_offset = (self.column_offset + 1).min(columns.saturating_sub(1));
+            }
+            KeyCode::Enter => match self.focus {
+                Focus::Sidebar => {
+                    if matches!(self.selected_item(), Some(SidebarItem::Settings)) {
+                        return self.run_classic(MenuState::Settings, terminal);
+                    }
+                    self.focus = Focus::Main;
+                }
+                Focus::Main => {
+                    if self.screen == MenuState::ViewLeads
+                        && let (Some(index), Some(mut list)) = (self.table_state.selected(), self.selected_list())
+                        && index < self.leads.len()
+                    {
+                        let lead = self.leads.remove(index);
+                        return self.suspended(terminal, |_| lead_detail(lead, &mut list));
+                    }
+                }
+            },
+            _ => {}
+        }
+        terminal
+    }
+
+    fn move_selection(&mut self, by: i32) {
+        match self.focus {
+            Focus::Sidebar => {
+                let last = self.sidebar.len().saturating_sub(1) as i32;
+                let current = self.sidebar_state.selected().unwrap_or(0) as i32;
+                self.sidebar_state.select(Some((current + by).clamp(0, last) as usize));
+                self.column_offset = 0;
+                self.table_state.select(Some(0));
+                self.open_selected();
+            }
+            Focus::Main => {
+                let rows = self.rows().1.len();
+                if rows == 0 {
+                    return;
+                }
+                let current = self.table_state.selected().unwrap_or(0) as i32;
+                self.table_state
+                    .select(Some((current + by).clamp(0, rows as i32 - 1) as usize));
+            }
+        }
+    }
+
+    fn open_popup(&mut self, kind: FetchKind) {
+        let Some(list) = self.selected_list() else {
+            self.set_error("select a list in the sidebar first".to_string());
+            return;
+        };
+        if matches!(kind, FetchKind::Emails) {
+            if list.leads_fetched == 0 {
+                self.set_error("you do not have any leads to fetch emails of, first fetch some leads.".to_string());
+                return;
+            }
+            if list.leads_fetched == list.emails_fetched {
+                self.set_error("all leads already have emails checked.".to_string());
+                return;
+            }
+        }
+        self.popup = Some(CountPopup {
+            kind,
+            list,
+            input: String::new(),
+            error: None,
+        });
+    }
+
+    fn on_popup_key(&mut self, key: KeyEvent) {
+        let Some(popup) = self.popup.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.popup = None,
+            KeyCode::Backspace => {
+                popup.input.pop();
+            }
+            KeyCode::Char(c) if c.is_ascii_digit() => popup.input.push(c),
+            KeyCode::Enter => {
+                let count = popup.input.trim().parse::<u32>().unwrap_or(0);
+                let max_possible = popup.list.leads_fetched - popup.list.emails_fetched;
+                if count == 0 {
+                    popup.error = Some("please enter a positive integer".to_string());
+                } else if matches!(popup.kind, FetchKind::Emails) && count > max_possible {
+                    popup.error = Some(format!(
+                        "the maximum number of emails that can be fetched is {}",
+                        max_possible
+                    ));
+                } else if let Some(popup) = self.popup.take() {
+                    self.start_job(popup.kind, popup.list, count);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn start_job(&mut self, kind: FetchKind, mut list: List, count: u32) {
+        let list_lock = match ListLock::acquire(&list) {
+            Ok(list_lock) => list_lock,
+            Err(e) => {
+                self.set_error(e.to_string());
+                return;
+            }
+        };
+        let purpose = match kind {
+            FetchKind::Leads => ApiKeyFor::Leads,
+            FetchKind::Emails => ApiKeyFor::Email,
+        };
+        let apikeys = match api_keys_for(&purpose) {
+            //the SMTP verifier asks the mail servers, it doesn't need a key
+            Ok(_) if matches!(kind, FetchKind::Emails) && !emails::uses_api() => Vec::new(),
+            Ok(keys) if keys.is_empty() => {
+                self.set_error("you don't have any API keys, create one to continue (press a)".to_string());
+                return;
+            }
+            Ok(keys) => keys,
+            Err(e) => {
+                self.set_error(format!("couldn't fetch API keys from DB: {}", e));
+                return;
+            }
+        };
+        let reporter = Reporter::Channel {
+            job: self.jobs.len(),
+            sender: self.sender.clone(),
+        };
+        let mut title = match kind {
+            FetchKind::Leads => format!("{} leads for {}", count, list.name),
+            FetchKind::Emails => format!("{} emails for {}", count, list.name),
+        };
+        if jobs::dry_run() {
+            title = format!("dry run: {}", title);
+        }
+        let handle = thread::spawn(move || match kind {
+            FetchKind::Leads => {
+                run_leads_job(&mut list, list_lock, count, apikeys, &reporter);
+            }
+            FetchKind::Emails => run_emails_job(&mut list, list_lock, count, apikeys, &reporter),
+        });
+        self.jobs.push(Job {
+            title: title.clone(),
+            log: Vec::new(),
+            progress: None,
+            finished: false,
+            handle: Some(handle),
+        });
+        self.set_info(format!("started fetching {}", title));
+        self.show_screen(MenuState::Fetch);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+        let [sidebar, main] =
+            Layout::horizontal([Constraint::Length(28), Constraint::Min(0)]).areas(body);
+
+        let running = self.running_jobs();
+        let mut title = vec![
+            Span::from(" apollo ").bold().green(),
+            Span::from(format!("[{}] ", profile::active())).green(),
+            Span::from(screen_name(self.screen)).blue(),
+        ];
+        if running > 0 {
+            title.push(Span::from(format!("  {} job(s) running", running)).yellow());
+        }
+        frame.render_widget(Line::from(title), header);
+
+        self.draw_sidebar(frame, sidebar);
+        match self.screen {
+            MenuState::Fetch => self.draw_jobs(frame, main),
+            MenuState::Main => self.draw_overview(frame, main),
+            _ => self.draw_table(frame, main),
+        }
+        self.draw_footer(frame, footer);
+        if self.popup.is_some() {
+            self.draw_popup(frame);
+        }
+    }
+
+    fn border_style(&self, focus: Focus) -> Style {
+        if self.focus == focus {
+            Style::new().fg(Color::Green)
+        } else {
+            Style::new().fg(Color::DarkGray)
+        }
+    }
+
+    fn draw_sidebar(&mut self, frame: &mut Frame, area: Rect) {
+        let running = self.running_jobs();
+        let items = self
+            .sidebar
+            .iter()
+            .map(|item| match item {
+                SidebarItem::Overview => ListItem::new("Overview"),
+                SidebarItem::Lists => ListItem::new("Lists").bold(),
+                SidebarItem::List(index) => {
+                    let list = &self.lists[*index];
+                    ListItem::new(format!("  {} ({}/{})", list.name, list.emails_fetched, list.leads_fetched))
+                }
+                SidebarItem::ApiKeys => ListItem::new("API Keys").bold(),
+                SidebarItem::Jobs if running > 0 => {
+                    ListItem::new(format!("Jobs ({} running)", running)).bold()
+                }
+                SidebarItem::Jobs => ListItem::new("Jobs").bold(),
+                SidebarItem::Settings => ListItem::new("Settings").bold(),
+            })
+            .collect::<Vec<ListItem>>();
+        let sidebar = ListWidget::new(items)
+            .block(Block::bordered().title("apollo").border_style(self.border_style(Focus::Sidebar)))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(sidebar, area, &mut self.sidebar_state);
+    }
+
+    fn draw_overview(&self, frame: &mut Frame, area: Rect) {
+        let mut lines = Vec::new();
+        if self.first_time {
+            lines.push(Line::from("This is your first time using Apollo, please consider setting an API key first.").blue());
+            lines.push(Line::from(""));
+        }
+        lines.push(Line::from(format!(
+            "{} lists, {} API keys, {} jobs this session",
+            self.lists.len(),
+            self.api_keys.len(),
+            self.jobs.len()
+        )));
+        lines.push(Line::from(""));
+        for (keys, help) in SHORTCUTS {
+            lines.push(Line::from(vec![
+                Span::from(format!("{:<14}", keys)).green(),
+                Span::from(help),
+            ]));
+        }
+        let overview = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title("Overview").border_style(self.border_style(Focus::Main)));
+        frame.render_widget(overview, area);
+    }
+
+    fn draw_table(&mut self, frame: &mut Frame, area: Rect) {
+        let (headers, rows) = self.rows();
+        let title = match (self.screen, self.selected_list()) {
+            (MenuState::ViewLeads, Some(list)) => format!("leads for the list {} ({})", list.name, rows.len()),
+            (screen, _) => screen_name(screen).to_string(),
+        };
+        let offset = self.column_offset.min(headers.len().saturating_sub(1));
+        let widths = (offset..headers.len())
+            .map(|column| {
+                let widest = rows
+                    .iter()
+                    .map(|row| row[column].chars().count())
+                    .chain([headers[column].chars().count()])
+                    .max()
+                    .unwrap_or(0);
+                Constraint::Length(widest.min(MAX_COLUMN_WIDTH) as u16)
+            })
+            .collect::<Vec<Constraint>>();
+        let header = Row::new(headers.iter().skip(offset).map(|h| Cell::from(h.clone())))
+            .style(Style::new().bold().fg(Color::Green));
+        let body = rows
+            .into_iter()
+            .map(|row| Row::new(row.into_iter().skip(offset).map(Cell::from)));
+        let mut block_title = title;
+        if offset > 0 {
+            block_title.push_str(format!(" - columns {}+ (h/l to scroll)", offset + 1).as_str());
+        }
+        let table = Table::new(body, widths)
+            .header(header)
+            .column_spacing(2)
+            .block(Block::bordered().title(block_title).border_style(self.border_style(Focus::Main)))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.table_state);
+    }
+
+    fn draw_jobs(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title("Jobs").border_style(self.border_style(Focus::Main));
+        if self.jobs.is_empty() {
+            let empty = Paragraph::new("no jobs yet, select a list and press f to fetch leads or e to fetch emails")
+                .block(block);
+            frame.render_widget(empty, area);
+            return;
+        }
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let areas = Layout::vertical(self.jobs.iter().rev().map(|_| Constraint::Min(5)))
+            .split(inner);
+        for (job, job_area) in self.jobs.iter().rev().zip(areas.iter()) {
+            let status = if job.finished {
+                Span::from(" done ").green()
+            } else {
+                Span::from(" running ").yellow()
+            };
+            let job_block = Block::bordered().title(Line::from(vec![Span::from(job.title.clone()), status]));
+            let job_inner = job_block.inner(*job_area);
+            frame.render_widget(job_block, *job_area);
+            let [gauge_area, log_area] =
+                Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(job_inner);
+            if let Some(progress) = &job.progress {
+                let gauge = Gauge::default()
+                    .gauge_style(Style::new().fg(Color::Green).bg(Color::DarkGray))
+                    .ratio(progress.ratio())
+                    .label(progress.summary());
+                frame.render_widget(gauge, gauge_area);
+            }
+            let visible = log_area.height as usize;
+            let lines = job
+                .log
+                .iter()
+                .filter_map(|event| match event {
+                    JobEvent::Info(message) => Some(Line::from(message.clone())),
+                    JobEvent::Error(message) => Some(Line::from(message.clone()).red()),
+                    JobEvent::Progress(_) | JobEvent::Finished => None,
+                })
+                .collect::<Vec<Line>>();
+            let skip = lines.len().saturating_sub(visible);
+            let log = Paragraph::new(lines.into_iter().skip(skip).collect::<Vec<Line>>());
+            frame.render_widget(log, log_area);
+        }
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let [status, keys] = Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(area);
+        if let Some((is_error, message)) = &self.status {
+            let line = if *is_error {
+                Line::from(message.clone()).red()
+            } else {
+                Line::from(message.clone()).blue()
+            };
+            frame.render_widget(line, status);
+        }
+        let shortcuts = "q quit  tab focus  ↑↓ move  ←→ scroll  enter open  f fetch leads  e fetch emails  n new list  a add key  s settings  m menu  r reload";
+        frame.render_widget(Line::from(shortcuts).dark_gray(), keys);
+    }
+
+    fn draw_popup(&self, frame: &mut Frame) {
+        let Some(popup) = &self.popup else {
+            return;
+        };
+        let area = centered(frame.area(), 60, 6);
+        let question = match popup.kind {
+            FetchKind::Leads => format!("how many leads do you want to fetch for {} ?", popup.list.name),
+            FetchKind::Emails => format!("how many emails do you want to fetch for {} ?", popup.list.name),
+        };
+        let mut lines = vec![Line::from(question), Line::from(format!("> {}", popup.input)).green()];
+        if let Some(error) = &popup.error {
+            lines.push(Line::from(error.clone()).red());
+        }
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title("Fetch (enter to start, esc to cancel)")),
+            area,
+        );
+    }
+}
+
+const SHORTCUTS: [(&str, &str); 11] = [
+    ("tab", "switch focus between the sidebar and the screen"),
+    ("↑ ↓ / j k", "move, page up/down to jump"),
+    ("← → / h l", "scroll wide tables horizontally"),
+    ("enter", "open the selected entry or lead"),
+    ("f", "fetch leads for the selected list"),
+    ("e", "fetch emails for the selected list"),
+    ("n", "add a new list"),
+    ("a", "add a new API key"),
+    ("s", "settings"),
+    ("m", "open the classic menu"),
+    ("q / esc", "quit"),
+];
+
+fn screen_name(screen: MenuState) -> &'static str {
+    match screen {
+        MenuState::Main | MenuState::Export | MenuState::Profiles => "Overview",
+        MenuState::Lists => "Lists",
+        MenuState::ViewLeads | MenuState::Companies => "Leads",
+        MenuState::APIkeys => "API Keys",
+        MenuState::Fetch | MenuState::FetchLeads | MenuState::FetchEmails => "Jobs",
+        MenuState::Settings | MenuState::GeneralSettings | MenuState::NetworkSettings | MenuState::Database => "Settings",
+        MenuState::GoodBye => "",
+    }
+}
+
+fn tabular<T: Tablular>(items: &[T]) -> (Vec<String>, Vec<Vec<String>>) {
+    (
+        T::headers().into_iter().map(|h| h.to_string()).collect(),
+        items.iter().map(|item| item.row()).collect(),
+    )
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)]).flex(Flex::Center).areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center).areas(area);
+    area
+}
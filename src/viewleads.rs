@@ -1,32 +1,391 @@
+use colorize::AnsiColor;
+use inquire::{Confirm, Select, Text, validator::Validation};
+use prettytable::Table;
 
+use crate::{classify::EmailKind, clear_and_logo, config, error::AppResult, jobs::ListLock, db::{list_all, list_selection, tabular_output, DBentity, ListSelectedResult, PageConfig, PagedResult}, empty_validator, leads::{confidence_label, kind_label, num_validator, Lead, LeadField}, list::List, MenuState};
 
-use inquire::Confirm;
-use crate::{db::{list_all, list_selection, tabular_output, DBentity, ListSelectedResult}, leads::Lead, list::List, MenuState};
+/// leads shown per page, `view.page_size`
+fn page_size() -> u32 {
+    config::get_u64("view.page_size").clamp(1, 1000) as u32
+}
 
-pub fn view_leads() -> MenuState {
-     let selection_maybe = list_selection();
-    let selected_list_maybe: Option<List>;
-    if let Err(e) = selection_maybe {
-        println!("something went wrong {}", e);
-        return MenuState::GoodBye;
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+    Added,
+    Name,
+    Title,
+    Organization,
+    Email,
+    Country,
+}
+
+impl SortColumn {
+    const ALL: [SortColumn; 6] = [
+        Self::Added,
+        Self::Name,
+        Self::Title,
+        Self::Organization,
+        Self::Email,
+        Self::Country,
+    ];
+
+    fn to_str(self) -> &'static str {
+        match self {
+            Self::Added => "date added",
+            Self::Name => "name",
+            Self::Title => "title",
+            Self::Organization => "organization",
+            Self::Email => "email",
+            Self::Country => "country",
+        }
     }
-    let selection = selection_maybe.unwrap();
-    match selection {
-        ListSelectedResult::Back => {return MenuState::Fetch},
-        ListSelectedResult::NoLists => {return MenuState::Lists},
-        ListSelectedResult::ListSelected(list) => {selected_list_maybe = Some(list)}
-    };
-    let selected_list = selected_list_maybe.unwrap();
 
-    //fetch all the leads from db
-    let all_leads = list_all::<Lead>(Some(format!("SELECT * FROM {} WHERE listId = {}",Lead::table_name(),&selected_list.id)), None);
-    match all_leads {
-        Err(e) => println!("{}: {}", "couldn't fetch leads from DB", e),
-        Ok(data) => tabular_output(&data.items, format!("all leads for the list {}", selected_list.name)),
+    fn column(self) -> &'static str {
+        match self {
+            Self::Added => "rowid",
+            Self::Name => "name",
+            Self::Title => "title",
+            Self::Organization => "org_name",
+            Self::Email => "email",
+            Self::Country => "country",
+        }
+    }
+}
+
+/// state of the lead browser for a single list
+struct LeadBrowser {
+    list: List,
+    page: u32,
+    search: Option<String>,
+    sort: SortColumn,
+    descending: bool,
+    only_with_email: bool,
+    /// only leads whose email is of this kind
+    kind: Option<EmailKind>,
+}
+
+/// escapes user input so it can be used inside a quoted LIKE pattern
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('\'', "''");
+    format!("'%{}%'", escaped)
+}
+
+impl LeadBrowser {
+    fn new(list: List) -> Self {
+        Self {
+            list,
+            page: 0,
+            search: None,
+            sort: SortColumn::Added,
+            descending: false,
+            only_with_email: false,
+            kind: None,
+        }
+    }
+
+    fn query(&self) -> String {
+        let mut conditions = vec![format!("listId = {}", self.list.id)];
+        if self.only_with_email {
+            conditions.push("email IS NOT NULL AND email != ''".to_string());
+        }
+        if let Some(kind) = self.kind {
+            conditions.push(format!("emailKind = '{}'", kind.to_str()));
+        }
+        if let Some(search) = &self.search {
+            let pattern = like_pattern(search);
+            let matches = ["name", "title", "org_name", "email"]
+                .iter()
+                .map(|column| format!("{} LIKE {} ESCAPE '\\'", column, pattern))
+                .collect::<Vec<String>>()
+                .join(" OR ");
+            conditions.push(format!("({})", matches));
+        }
+        format!(
+            "SELECT * FROM {} WHERE {} ORDER BY {} {}",
+            Lead::table_name(),
+            conditions.join(" AND "),
+            self.sort.column(),
+            if self.descending { "DESC" } else { "ASC" }
+        )
+    }
+
+    fn fetch_page(&self) -> Result<PagedResult<Lead>, rusqlite::Error> {
+        list_all::<Lead>(
+            Some(self.query()),
+            Some(PageConfig {
+                rows: page_size(),
+                offset: self.page * page_size(),
+            }),
+        )
+    }
+
+    fn heading(&self, total_rows: u32) -> String {
+        let mut heading = format!(
+            "leads for the list {} - page {} of {} ({} leads)",
+            self.list.name,
+            self.page + 1,
+            total_pages(total_rows),
+            total_rows
+        );
+        if let Some(search) = &self.search {
+            heading.push_str(format!(", matching \"{}\"", search).as_str());
+        }
+        if self.only_with_email {
+            heading.push_str(", only with email");
+        }
+        if let Some(kind) = self.kind {
+            heading.push_str(format!(", only {} emails", kind.to_str()).as_str());
+        }
+        heading.push_str(
+            format!(
+                ", sorted by {} {}",
+                self.sort.to_str(),
+                if self.descending { "descending" } else { "ascending" }
+            )
+            .as_str(),
+        );
+        heading
+    }
+}
+
+fn total_pages(total_rows: u32) -> u32 {
+    total_rows.div_ceil(page_size()).max(1)
+}
+
+pub fn view_leads() -> AppResult<MenuState> {
+    let mut browser = match list_selection()? {
+        ListSelectedResult::Back => return Ok(MenuState::Main),
+        ListSelectedResult::NoLists => return Ok(MenuState::Lists),
+        ListSelectedResult::ListSelected(list) => LeadBrowser::new(*list),
     };
 
-    //dont go back and hide the  table
-    let _ = Confirm::new("go back ?").with_default(true).prompt();
-    return MenuState::Main;
+    loop {
+        let page = browser.fetch_page()?;
+        let pages = total_pages(page.total_rows);
+        //the last page can disappear after deleting or moving leads
+        if page.items.is_empty() && browser.page > 0 {
+            browser.page = pages - 1;
+            continue;
+        }
+        tabular_output(&page.items, browser.heading(page.total_rows));
+
+        let mut options = Vec::new();
+        if !page.items.is_empty() {
+            options.push("Open lead");
+        }
+        if page.next.is_some() {
+            options.push("Next page");
+        }
+        if browser.page > 0 {
+            options.push("Previous page");
+        }
+        if pages > 1 {
+            options.push("Jump to page");
+        }
+        options.push("Search");
+        if browser.search.is_some() {
+            options.push("Clear search");
+        }
+        options.push("Sort by");
+        options.push(if browser.only_with_email {
+            "Show all leads"
+        } else {
+            "Show only leads with email"
+        });
+        options.push("Filter by email kind");
+        if browser.kind.is_some() {
+            options.push("Clear email kind filter");
+        }
+        options.push("Back");
 
-}
\ No newline at end of file
+        let selection = Select::new("Leads".green().as_str(), options).prompt();
+        match selection {
+            Ok("Open lead") => {
+                let labels = page.items.iter().map(|lead| lead.label()).collect::<Vec<String>>();
+                let picked = Select::new("which lead ?", labels).raw_prompt();
+                if let Ok(picked) = picked
+                    && let Some(lead) = page.items.into_iter().nth(picked.index)
+                {
+                    lead_detail(lead, &mut browser.list);
+                }
+            }
+            Ok("Next page") => browser.page += 1,
+            Ok("Previous page") => browser.page -= 1,
+            Ok("Jump to page") => {
+                let page_number = Text::new(format!("which page (1-{}) ?", pages).as_str())
+                    .with_validators(&[
+                        Box::new(empty_validator),
+                        Box::new(num_validator),
+                        Box::new(move |v: &str| {
+                            let page_number = v.trim().parse::<u32>().unwrap_or(0);
+                            if page_number == 0 || page_number > pages {
+                                Ok(Validation::Invalid(
+                                    format!("please enter a page between 1 and {}", pages).into(),
+                                ))
+                            } else {
+                                Ok(Validation::Valid)
+                            }
+                        }),
+                    ])
+                    .prompt();
+                if let Ok(page_number) = page_number
+                    && let Ok(page_number) = page_number.trim().parse::<u32>()
+                {
+                    browser.page = page_number.saturating_sub(1);
+                }
+            }
+            Ok("Search") => {
+                let search = Text::new("search name, title, organization or email:")
+                    .with_validator(empty_validator)
+                    .prompt();
+                if let Ok(search) = search {
+                    browser.search = Some(search.trim().to_string());
+                    browser.page = 0;
+                }
+            }
+            Ok("Clear search") => {
+                browser.search = None;
+                browser.page = 0;
+            }
+            Ok("Sort by") => {
+                let columns = SortColumn::ALL.iter().map(|c| c.to_str()).collect::<Vec<_>>();
+                let column = Select::new("sort by", columns).prompt();
+                let direction = Select::new("direction", vec!["ascending", "descending"]).prompt();
+                if let (Ok(column), Ok(direction)) = (column, direction) {
+                    browser.sort = SortColumn::ALL
+                        .into_iter()
+                        .find(|c| c.to_str() == column)
+                        .unwrap_or(SortColumn::Added);
+                    browser.descending = direction == "descending";
+                    browser.page = 0;
+                }
+            }
+            Ok("Show all leads") | Ok("Show only leads with email") => {
+                browser.only_with_email = !browser.only_with_email;
+                browser.page = 0;
+            }
+            Ok("Filter by email kind") => {
+                let kinds = EmailKind::ALL.iter().map(|kind| kind.to_str()).collect::<Vec<_>>();
+                if let Ok(picked) = Select::new("which kind of email ?", kinds).raw_prompt() {
+                    browser.kind = Some(EmailKind::ALL[picked.index]);
+                    browser.page = 0;
+                }
+            }
+            Ok("Clear email kind filter") => {
+                browser.kind = None;
+                browser.page = 0;
+            }
+            Ok(_) => return Ok(MenuState::Main),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn print_lead(lead: &Lead, list: &List) {
+    let mut table = Table::new();
+    table.add_row(prettytable::row!["field", "value"]);
+    for field in LeadField::ALL {
+        table.add_row(prettytable::row![
+            field.to_str(),
+            lead.get(field).unwrap_or("not available".to_string())
+        ]);
+    }
+    if lead.email.is_some() {
+        table.add_row(prettytable::row!["email confidence", confidence_label(lead.email_confidence)]);
+        table.add_row(prettytable::row!["email kind", kind_label(lead.email_kind)]);
+    }
+    table.add_row(prettytable::row!["id", lead.id]);
+    table.add_row(prettytable::row!["list", list.name]);
+    clear_and_logo(format!("lead {}", lead.get(LeadField::Name).unwrap_or_default()));
+    table.printstd();
+}
+
+/// shows every stored field of a lead and lets the user correct, delete or move it
+pub fn lead_detail(mut lead: Lead, list: &mut List) {
+    loop {
+        print_lead(&lead, list);
+        let options = vec!["Edit field", "Delete lead", "Move to another list", "Back"];
+        match Select::new("Lead".green().as_str(), options).prompt() {
+            Ok("Edit field") => {
+                let labels = LeadField::ALL.iter().map(|f| f.to_str()).collect::<Vec<_>>();
+                let Ok(picked) = Select::new("which field ?", labels).raw_prompt() else {
+                    continue;
+                };
+                let field = LeadField::ALL[picked.index];
+                let current = lead.get(field).unwrap_or_default();
+                let message = format!("new {}:", field.to_str());
+                let mut prompt = Text::new(message.as_str()).with_initial_value(current.as_str());
+                if !field.is_optional() {
+                    prompt = prompt.with_validator(empty_validator);
+                }
+                let Ok(value) = prompt.prompt() else {
+                    continue;
+                };
+                let value = match value.trim() {
+                    "" => None,
+                    val => Some(val.to_string()),
+                };
+                match lead.update_field(field, value) {
+                    Ok(_) => println!("{}", "lead updated".blue()),
+                    Err(e) => println!("{}: {}", "couldn't update the lead".red(), e),
+                }
+            }
+            Ok("Delete lead") => {
+                let confirmed = Confirm::new("delete this lead ?").with_default(false).prompt();
+                if !matches!(confirmed, Ok(true)) {
+                    continue;
+                }
+                //a job on the list counts its leads by position
+                let _list_lock = match ListLock::acquire(list) {
+                    Ok(list_lock) => list_lock,
+                    Err(e) => {
+                        println!("{}", e.to_string().red());
+                        continue;
+                    }
+                };
+                match lead.delete(list) {
+                    Ok(_) => println!("{}", "lead deleted".blue()),
+                    Err(e) => println!("{}: {}", "couldn't delete the lead".red(), e),
+                }
+                return;
+            }
+            Ok("Move to another list") => {
+                let other_lists = match list_all::<List>(List::custom_query().map(|v| v.to_string()), None) {
+                    Ok(lists) => lists.items.into_iter().filter(|l| l.id != list.id).collect::<Vec<List>>(),
+                    Err(e) => {
+                        println!("{}: {}", "couldn't fetch lists from DB".red(), e);
+                        continue;
+                    }
+                };
+                if other_lists.is_empty() {
+                    println!("{}", "there is no other list to move this lead to".red());
+                    continue;
+                }
+                let names = other_lists.iter().map(|l| l.name.clone()).collect::<Vec<String>>();
+                let Ok(picked) = Select::new("move to which list ?", names).raw_prompt() else {
+                    continue;
+                };
+                let Some(mut target) = other_lists.into_iter().nth(picked.index) else {
+                    continue;
+                };
+                let _list_locks = match ListLock::acquire(list).and_then(|from| Ok((from, ListLock::acquire(&target)?))) {
+                    Ok(list_locks) => list_locks,
+                    Err(e) => {
+                        println!("{}", e.to_string().red());
+                        continue;
+                    }
+                };
+                match lead.move_to(list, &mut target) {
+                    Ok(_) => println!("{} {}", "lead moved to".blue(), target.name.clone().blue()),
+                    Err(e) => println!("{}: {}", "couldn't move the lead".red(), e),
+                }
+                return;
+            }
+            _ => return,
+        }
+    }
+}